@@ -0,0 +1,276 @@
+//! Ways of resolving a duplicate file
+//!
+//! A duplicate can be deleted, replaced by a hard or symbolic link to the
+//! file that is kept, or moved out of the scanned tree. Every successful
+//! operation returns a [`JournalEntry`] describing what was done.
+
+use crate::journal::JournalEntry;
+use crate::FileInfo;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The kind of operation applied to a duplicate file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    /// Remove the duplicate
+    Delete,
+    /// Replace the duplicate with a hard link to the kept file
+    Hardlink,
+    /// Replace the duplicate with a symbolic link to the kept file
+    Symlink,
+    /// Move the duplicate into a separate directory
+    Move,
+}
+
+impl ActionKind {
+    /// Past-tense verb used in summaries, e.g. "deleted"
+    pub fn past_tense(self) -> &'static str {
+        match self {
+            ActionKind::Delete => "deleted",
+            ActionKind::Hardlink => "hardlinked",
+            ActionKind::Symlink => "symlinked",
+            ActionKind::Move => "moved",
+        }
+    }
+}
+
+impl std::fmt::Display for ActionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ActionKind::Delete => "delete",
+            ActionKind::Hardlink => "hardlink",
+            ActionKind::Symlink => "symlink",
+            ActionKind::Move => "move",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An operation to apply to every non-kept file of a duplicate group
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Delete,
+    Hardlink,
+    Symlink,
+    /// Move duplicates below this directory, preserving their original path
+    Move(PathBuf),
+}
+
+impl Action {
+    /// Build an action of the given kind; `move_to` is required for moves
+    ///
+    /// A relative `move_to` is made absolute, so the journal can find moved
+    /// files again from any directory.
+    pub fn new(kind: ActionKind, move_to: Option<PathBuf>) -> anyhow::Result<Self> {
+        Ok(match kind {
+            ActionKind::Delete => Action::Delete,
+            ActionKind::Hardlink => Action::Hardlink,
+            ActionKind::Symlink => Action::Symlink,
+            ActionKind::Move => {
+                let dir = move_to
+                    .ok_or_else(|| anyhow::anyhow!("Moving duplicates needs a directory"))?;
+                Action::Move(std::path::absolute(dir)?)
+            }
+        })
+    }
+
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Action::Delete => ActionKind::Delete,
+            Action::Hardlink => ActionKind::Hardlink,
+            Action::Symlink => ActionKind::Symlink,
+            Action::Move(_) => ActionKind::Move,
+        }
+    }
+}
+
+/// Apply `action` to `file`, keeping `keeper` as the surviving copy
+///
+/// The file is re-checked before anything is changed: if it vanished or its
+/// size or hash no longer matches the scan, an error is returned and nothing
+/// happens. Unless the file is only moved, the keeper is checked the same
+/// way, so the content always survives somewhere. Files without a recorded
+/// hash must have the same content as the keeper.
+pub fn apply_action(
+    action: &Action,
+    file: &FileInfo,
+    keeper: &FileInfo,
+) -> anyhow::Result<JournalEntry> {
+    if file.path == keeper.path {
+        anyhow::bail!("Refusing to replace {} with itself", file.path.display());
+    }
+    // TOCTOU protection
+    verify_unchanged(file)?;
+    if !matches!(action, Action::Move(_)) {
+        verify_unchanged(keeper)?;
+        if file.hash.is_none() || file.hash != keeper.hash {
+            verify_same_content(file, keeper)?;
+        }
+    }
+
+    let mut destination = None;
+    match action {
+        Action::Delete => fs::remove_file(&file.path)?,
+        Action::Hardlink => {
+            replace_with(&file.path, |tmp| fs::hard_link(&keeper.path, tmp))?;
+        }
+        Action::Symlink => {
+            let target = fs::canonicalize(&keeper.path)?;
+            replace_with(&file.path, |tmp| symlink_file(&target, tmp))?;
+        }
+        Action::Move(dir) => {
+            let target = move_destination(dir, &file.path);
+            if target.exists() {
+                anyhow::bail!("Move destination {} already exists", target.display());
+            }
+            move_file(&file.path, &target)?;
+            destination = Some(target);
+        }
+    }
+
+    Ok(JournalEntry::new(action.kind(), file, keeper, destination))
+}
+
+/// Check that `file` still has the size, and hash if known, it had when it
+/// was scanned
+fn verify_unchanged(file: &FileInfo) -> anyhow::Result<()> {
+    let metadata = fs::symlink_metadata(&file.path)
+        .map_err(|_| anyhow::anyhow!("File {} no longer exists", file.path.display()))?;
+    if !metadata.is_file() || metadata.len() != file.size {
+        anyhow::bail!("File {} changed since the scan", file.path.display());
+    }
+    if let Some(hash) = file.hash {
+        // Hash afresh rather than trusting the cached value
+        let mut current = FileInfo::new(file.path.clone(), file.size);
+        if current.calculate_hash_with(hash.algorithm())? != hash {
+            anyhow::bail!("File {} changed since the scan", file.path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Check that `file` and `keeper` hold the same content right now
+fn verify_same_content(file: &FileInfo, keeper: &FileInfo) -> anyhow::Result<()> {
+    let hash = |info: &FileInfo| FileInfo::new(info.path.clone(), info.size).calculate_hash();
+    if file.size != keeper.size || hash(file)? != hash(keeper)? {
+        anyhow::bail!(
+            "File {} doesn't match {}",
+            file.path.display(),
+            keeper.path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Where `path` ends up when moved below `dir`
+///
+/// The full original path is recreated under `dir`, so files with the same
+/// name from different directories never collide.
+pub fn move_destination(dir: &Path, path: &Path) -> PathBuf {
+    let mut target = dir.to_path_buf();
+    for component in path.components() {
+        if let Component::Normal(part) = component {
+            target.push(part);
+        }
+    }
+    target
+}
+
+/// Move a file, falling back to copy and remove across filesystems
+pub fn move_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Atomically replace `path` with whatever `create` puts at a temporary path
+/// next to it
+pub(crate) fn replace_with(
+    path: &Path,
+    create: impl FnOnce(&Path) -> std::io::Result<()>,
+) -> anyhow::Result<()> {
+    let tmp = temp_sibling(path);
+    create(&tmp)?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Suffix of the temporary files used while replacing a file
+pub(crate) const TEMP_SUFFIX: &str = ".file-dedup-tmp";
+
+fn temp_sibling(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}{}", name, TEMP_SUFFIX))
+}
+
+#[cfg(unix)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_file;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_move_destination_recreates_path() {
+        let target = move_destination(Path::new("/trash"), Path::new("/home/user/a.txt"));
+        assert_eq!(target, PathBuf::from("/trash/home/user/a.txt"));
+    }
+
+    #[test]
+    fn test_hardlink_replaces_duplicate() {
+        let temp_dir = TempDir::new().unwrap();
+        let keeper = write_file(temp_dir.path(), "keep.txt", b"same content");
+        let dup = write_file(temp_dir.path(), "dup.txt", b"same content");
+
+        let entry = apply_action(&Action::Hardlink, &dup, &keeper).unwrap();
+
+        assert_eq!(entry.action, ActionKind::Hardlink);
+        assert_eq!(entry.keeper, keeper.path);
+        assert_eq!(fs::read(&dup.path).unwrap(), b"same content");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let keeper_ino = fs::metadata(&keeper.path).unwrap().ino();
+            assert_eq!(fs::metadata(&dup.path).unwrap().ino(), keeper_ino);
+        }
+    }
+
+    #[test]
+    fn test_changed_file_is_not_touched() {
+        let temp_dir = TempDir::new().unwrap();
+        let keeper = write_file(temp_dir.path(), "keep.txt", b"same content");
+        let dup = write_file(temp_dir.path(), "dup.txt", b"same content");
+        fs::write(&dup.path, b"edited after the scan").unwrap();
+
+        assert!(apply_action(&Action::Delete, &dup, &keeper).is_err());
+        assert!(dup.path.exists());
+
+        // Same size, different content: only the hash re-check catches it
+        fs::write(&dup.path, b"SAME CONTENT").unwrap();
+        assert!(apply_action(&Action::Delete, &dup, &keeper).is_err());
+        assert!(dup.path.exists());
+    }
+}
@@ -0,0 +1,298 @@
+//! Memory-bounded scanning, used by [`Scanner::memory_limit`]
+//!
+//! [`crate::collect_files`] keeps a [`FileInfo`] for every file it finds,
+//! which doesn't fit in memory for tens of millions of files. The bounded
+//! scan walks the roots twice instead. The first pass only counts the files
+//! and path bytes of every size. The second pass keeps just the files whose
+//! size occurs more than once, packed into batches of whole sizes that each
+//! fit the memory limit. With more than one batch the files are spilled to
+//! temporary files and hashed one batch at a time.
+//!
+//! The limit covers the files held for hashing; the per-size counts and the
+//! duplicate groups found come on top. A single size with more files than
+//! the limit allows still forms one batch.
+
+use crate::error::{Error, Result};
+use crate::progress::{ProgressEvent, ProgressObserver};
+use crate::scanner::{ScanResult, Scanner};
+use crate::{bytes_to_path, path_to_bytes, DuplicateGroup, FileInfo};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Most batch files written at once, well below common open file limits
+const MAX_OPEN_SPILLS: usize = 64;
+
+/// Files seen with one size, and the batch they are hashed in
+#[derive(Default)]
+struct SizeCount {
+    files: u64,
+    path_bytes: u64,
+    batch: usize,
+}
+
+impl SizeCount {
+    /// Estimated memory needed to hold these files as [`FileInfo`]s
+    fn footprint(&self) -> u64 {
+        self.files * std::mem::size_of::<FileInfo>() as u64 + self.path_bytes
+    }
+}
+
+/// Run `scanner` holding at most about `memory_limit` bytes of file
+/// information at a time, filling in `result` and passing the groups found
+/// to `on_group`
+///
+/// Files are reported to `progress`, and walk warnings collected, during the
+/// first pass only.
+pub(crate) fn scan(
+    scanner: &Scanner,
+    memory_limit: u64,
+    progress: &mut dyn ProgressObserver,
+    result: &mut ScanResult,
+    on_group: &mut dyn FnMut(DuplicateGroup),
+) -> Result<()> {
+    // First pass: only count files per size
+    let mut sizes: HashMap<u64, SizeCount> = HashMap::new();
+    scanner.walk(
+        |file, size| {
+            progress.on_progress(ProgressEvent::FileFound { size });
+            let count = sizes.entry(size).or_default();
+            count.files += 1;
+            count.path_bytes += file.as_os_str().len() as u64;
+        },
+        &mut result.warnings,
+    );
+    let stats = &mut result.stats;
+    for (size, count) in &sizes {
+        stats.files_found += count.files;
+        stats.bytes_found += size * count.files;
+    }
+    sizes.retain(|_, count| count.files > 1);
+
+    // Pack sizes into batches that fit the limit
+    let mut candidate_sizes: Vec<u64> = sizes.keys().copied().collect();
+    candidate_sizes.sort_unstable();
+    let mut batches = 0;
+    let mut batch_bytes = 0;
+    for size in candidate_sizes {
+        let count = sizes.get_mut(&size).expect("size was counted");
+        if batches == 0 || (batch_bytes > 0 && batch_bytes + count.footprint() > memory_limit) {
+            batches += 1;
+            batch_bytes = 0;
+        }
+        count.batch = batches - 1;
+        batch_bytes += count.footprint();
+    }
+
+    stats.batches = batches;
+    stats.files_hashed = sizes.values().map(|c| c.files).sum();
+    stats.bytes_hashed = sizes.iter().map(|(size, c)| size * c.files).sum();
+    progress.on_progress(ProgressEvent::HashingStarted {
+        files: stats.files_hashed,
+        bytes: stats.bytes_hashed,
+    });
+
+    // Second pass: keep only the files that can have duplicates. Anything
+    // unreadable was reported by the first pass.
+    let mut walk_warnings = Vec::new();
+    if batches <= 1 {
+        let mut files_by_size: HashMap<u64, Vec<FileInfo>> = HashMap::new();
+        scanner.walk(
+            |file, size| {
+                if sizes.contains_key(&size) {
+                    files_by_size
+                        .entry(size)
+                        .or_default()
+                        .push(FileInfo::new(file.to_path_buf(), size));
+                }
+            },
+            &mut walk_warnings,
+        );
+        scanner.hash_batch(files_by_size, progress, &mut result.warnings, on_group);
+    } else {
+        let spill_dir = tempfile::tempdir().map_err(|e| Error::io(std::env::temp_dir(), e))?;
+        let batch_path = |batch: usize| spill_dir.path().join(format!("batch-{}", batch));
+
+        let spill_path = spill_dir.path().join("all");
+
+        // One spill file during the walk, split into batches afterwards
+        let mut writer = File::create(&spill_path)
+            .map(BufWriter::new)
+            .map_err(|e| Error::io(&spill_path, e))?;
+        let mut write_error = None;
+        scanner.walk(
+            |file, size| {
+                if sizes.contains_key(&size) {
+                    if let Err(e) = write_record(&mut writer, size, file) {
+                        write_error.get_or_insert(e);
+                    }
+                }
+            },
+            &mut walk_warnings,
+        );
+        if let Some(e) = write_error {
+            return Err(Error::io(&spill_path, e));
+        }
+        writer.flush().map_err(|e| Error::io(&spill_path, e))?;
+        drop(writer);
+
+        for first in (0..batches).step_by(MAX_OPEN_SPILLS) {
+            let chunk = first..batches.min(first + MAX_OPEN_SPILLS);
+            split_spill(&spill_path, &sizes, chunk.clone(), batch_path)?;
+            for batch in chunk {
+                let path = batch_path(batch);
+                let files_by_size = read_batch(&path).map_err(|e| Error::io(&path, e))?;
+                scanner.hash_batch(files_by_size, progress, &mut result.warnings, on_group);
+            }
+        }
+    }
+    progress.on_progress(ProgressEvent::HashingFinished);
+
+    Ok(())
+}
+
+// Spilled files are stored as size, path length and path bytes
+
+/// Copy the records of the batches in `chunk` from the spill at `spill_path`
+/// to a file per batch
+///
+/// Only one file per batch in `chunk` is open at a time, which keeps many
+/// batches from running into the open file limit.
+fn split_spill(
+    spill_path: &Path,
+    sizes: &HashMap<u64, SizeCount>,
+    chunk: Range<usize>,
+    batch_path: impl Fn(usize) -> PathBuf,
+) -> Result<()> {
+    let mut writers = chunk
+        .clone()
+        .map(|batch| {
+            let path = batch_path(batch);
+            File::create(&path)
+                .map(BufWriter::new)
+                .map_err(|e| Error::io(path, e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let file = File::open(spill_path).map_err(|e| Error::io(spill_path, e))?;
+    let mut reader = BufReader::new(file);
+    while let Some((size, file)) = read_record(&mut reader).map_err(|e| Error::io(spill_path, e))? {
+        let batch = sizes[&size].batch;
+        if chunk.contains(&batch) {
+            write_record(&mut writers[batch - chunk.start], size, &file)
+                .map_err(|e| Error::io(batch_path(batch), e))?;
+        }
+    }
+    for (batch, writer) in chunk.zip(&mut writers) {
+        writer
+            .flush()
+            .map_err(|e| Error::io(batch_path(batch), e))?;
+    }
+    Ok(())
+}
+
+/// Read back and remove the spilled batch at `path`
+fn read_batch(path: &Path) -> std::io::Result<HashMap<u64, Vec<FileInfo>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut files_by_size: HashMap<u64, Vec<FileInfo>> = HashMap::new();
+    while let Some((size, file)) = read_record(&mut reader)? {
+        files_by_size
+            .entry(size)
+            .or_default()
+            .push(FileInfo::new(file, size));
+    }
+    std::fs::remove_file(path)?;
+    Ok(files_by_size)
+}
+
+fn write_record(out: &mut impl Write, size: u64, path: &Path) -> std::io::Result<()> {
+    let bytes = path_to_bytes(path);
+    out.write_all(&size.to_le_bytes())?;
+    out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    out.write_all(&bytes)
+}
+
+fn read_record(input: &mut impl Read) -> std::io::Result<Option<(u64, PathBuf)>> {
+    let mut size = [0; 8];
+    match input.read_exact(&mut size) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut len = [0; 8];
+    input.read_exact(&mut len)?;
+    let mut bytes = vec![0; u64::from_le_bytes(len) as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(Some((u64::from_le_bytes(size), bytes_to_path(bytes))))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scanner::Scanner;
+    use crate::{collect_files, find_duplicate_groups, DuplicateGroup};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_bounded_scan_matches_full_scan() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for (name, content) in [
+            ("a1", "first"),
+            ("a2", "first"),
+            ("b1", "second!"),
+            ("b2", "second!"),
+            ("b3", "second!"),
+            ("c1", "not a duplicate"),
+            ("c2", "no duplicate ea"),
+            ("unique", "the only file of this size"),
+        ] {
+            fs::write(root.join(name), content).unwrap();
+        }
+
+        let mut files_by_size = HashMap::new();
+        let mut total_files = 0;
+        collect_files(root, &mut files_by_size, &mut total_files, &mut Vec::new()).unwrap();
+        let expected = find_duplicate_groups(files_by_size, &mut Vec::new()).unwrap();
+        let paths = |groups: &[DuplicateGroup]| -> Vec<Vec<PathBuf>> {
+            groups
+                .iter()
+                .map(|g| g.files.iter().map(|f| f.path.clone()).collect())
+                .collect()
+        };
+
+        // Everything in memory, and one batch per size
+        for (memory_limit, batches) in [(u64::MAX, 1), (1, 3)] {
+            let result = Scanner::new()
+                .root(root)
+                .memory_limit(memory_limit)
+                .scan()
+                .unwrap();
+            assert_eq!(paths(&result.groups), paths(&expected));
+            assert_eq!(result.stats.batches, batches);
+            assert_eq!(result.stats.files_found, 8);
+        }
+        assert_eq!(expected.len(), 2);
+    }
+
+    #[test]
+    fn test_many_batches() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        // A batch per size, more than can be open at once
+        let sizes = 2 * super::MAX_OPEN_SPILLS + 5;
+        for size in 1..=sizes {
+            for copy in 0..2 {
+                fs::write(root.join(format!("{}-{}", size, copy)), "x".repeat(size)).unwrap();
+            }
+        }
+
+        let result = Scanner::new().root(root).memory_limit(1).scan().unwrap();
+        assert_eq!(result.stats.batches, sizes);
+        assert_eq!(result.groups.len(), sizes);
+        assert!(result.groups.iter().all(|g| g.files.len() == 2));
+    }
+}
@@ -0,0 +1,31 @@
+//! Cooperative cancellation
+//!
+//! A [`CancellationToken`] is shared between a long operation and whoever
+//! may want to stop it, such as a Ctrl-C handler. Cancelling only sets a
+//! flag: scans check it between files and between read chunks and then fail
+//! with [`crate::error::Error::Cancelled`], and file operations check it
+//! between files, so every file is either handled completely or not at all.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A shared flag asking an operation to stop at the next safe point
+///
+/// Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation holding this token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
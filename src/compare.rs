@@ -0,0 +1,183 @@
+//! Compare mode: which source files already exist in a target
+//!
+//! Source and target roots are scanned with the normal size-then-hash
+//! pipeline, but only sizes present on both sides are hashed, and only
+//! groups with copies on both sides are reported. Duplicates within the
+//! source or within the target alone are ignored.
+//!
+//! The source files left over are the ones with no copy in the target, which
+//! is what the unique-files mode reports for backup verification.
+
+use crate::cancel::CancellationToken;
+use crate::error::{Error, Result};
+use crate::hash::{Digest, HashAlgorithm};
+use crate::progress::ProgressObserver;
+use crate::{collect_files_into, find_groups_into, FileInfo};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// A source file with the target files holding the same content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMatch {
+    pub source: PathBuf,
+    pub size: u64,
+    pub hash: Digest,
+    pub targets: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct Comparison {
+    /// Source files found in the target, sorted by path
+    pub matches: Vec<SourceMatch>,
+    /// Source files with no copy in the target, sorted by path
+    pub missing: Vec<FileInfo>,
+    /// Number of (non-empty) source files scanned
+    pub source_files: usize,
+    /// Files and directories on either side that were skipped, and why
+    pub warnings: Vec<Error>,
+}
+
+/// Find the files under `sources` whose content exists under `targets`
+///
+/// Fails with [`Error::Cancelled`] once `cancel` is cancelled.
+pub fn compare(
+    sources: &[PathBuf],
+    targets: &[PathBuf],
+    algorithm: HashAlgorithm,
+    cancel: &CancellationToken,
+    progress: &mut dyn ProgressObserver,
+) -> Result<Comparison> {
+    let mut warnings = Vec::new();
+    let mut source_by_size: HashMap<u64, Vec<FileInfo>> = HashMap::new();
+    let mut source_files = 0;
+    for path in sources {
+        collect_files_into(
+            path,
+            &mut source_by_size,
+            &mut source_files,
+            cancel,
+            progress,
+            &mut warnings,
+        );
+    }
+    let all_sources: Vec<FileInfo> = source_by_size.values().flatten().cloned().collect();
+    let source_paths: HashSet<PathBuf> = all_sources.iter().map(|f| f.path.clone()).collect();
+
+    let mut target_by_size: HashMap<u64, Vec<FileInfo>> = HashMap::new();
+    let mut target_files = 0;
+    for path in targets {
+        collect_files_into(
+            path,
+            &mut target_by_size,
+            &mut target_files,
+            cancel,
+            progress,
+            &mut warnings,
+        );
+    }
+
+    // Only sizes on both sides can match; a file under both roots is a source
+    let mut files_by_size: HashMap<u64, Vec<FileInfo>> = HashMap::new();
+    for (size, mut files) in source_by_size {
+        let Some(target) = target_by_size.remove(&size) else {
+            continue;
+        };
+        files.extend(
+            target
+                .into_iter()
+                .filter(|f| !source_paths.contains(&f.path)),
+        );
+        files_by_size.insert(size, files);
+    }
+
+    let mut matches = Vec::new();
+    let groups = find_groups_into(files_by_size, algorithm, cancel, progress, &mut warnings);
+    if cancel.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+    for group in groups {
+        let (in_source, in_target): (Vec<_>, Vec<_>) = group
+            .files
+            .into_iter()
+            .partition(|f| source_paths.contains(&f.path));
+        if in_target.is_empty() {
+            continue;
+        }
+        let targets: Vec<PathBuf> = in_target.into_iter().map(|f| f.path).collect();
+        for file in in_source {
+            matches.push(SourceMatch {
+                source: file.path,
+                size: group.size,
+                hash: group.hash,
+                targets: targets.clone(),
+            });
+        }
+    }
+    matches.sort_by(|a, b| a.source.cmp(&b.source));
+
+    let matched: HashSet<&PathBuf> = matches.iter().map(|m| &m.source).collect();
+    let mut missing: Vec<FileInfo> = all_sources
+        .into_iter()
+        .filter(|f| !matched.contains(&f.path))
+        .collect();
+    missing.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(Comparison {
+        matches,
+        missing,
+        source_files,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) -> PathBuf {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_compare_reports_only_cross_matches() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let imported = write(root, "dump/IMG_1.jpg", "photo one");
+        write(root, "dump/IMG_2.jpg", "photo two");
+        // Duplicated within the source only
+        write(root, "dump/IMG_3.jpg", "photo six");
+        write(root, "dump/IMG_3 copy.jpg", "photo six");
+        let library_copy = write(root, "library/2024/one.jpg", "photo one");
+        // Duplicated within the target only
+        write(root, "library/a.jpg", "photo ten");
+        write(root, "library/b.jpg", "photo ten");
+
+        let comparison = compare(
+            &[root.join("dump")],
+            &[root.join("library")],
+            HashAlgorithm::default(),
+            &CancellationToken::new(),
+            &mut crate::progress::NoProgress,
+        )
+        .unwrap();
+
+        assert_eq!(comparison.source_files, 4);
+        assert_eq!(comparison.missing.len(), 3);
+        assert!(comparison.missing.iter().all(|f| f.path != imported));
+        assert_eq!(
+            comparison.matches,
+            vec![SourceMatch {
+                source: imported,
+                size: 9,
+                hash: comparison.matches[0].hash,
+                targets: vec![library_copy],
+            }]
+        );
+    }
+}
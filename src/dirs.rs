@@ -0,0 +1,304 @@
+//! Duplicate directory detection
+//!
+//! Every directory gets a fingerprint built from the names and content hashes
+//! of everything below it, so two directories share a fingerprint exactly when
+//! their trees are identical. The content hashes come from the duplicate
+//! groups: a file without a duplicate anywhere makes its directory unique, so
+//! no extra hashing is needed.
+//!
+//! Like the file scan, empty files and symlinks are ignored, and so are
+//! directories that end up with no files at all.
+
+use crate::hash::{Digest, HashAlgorithm};
+use crate::vfs::{FileSystem, FileType, LocalFs};
+use crate::{DuplicateGroup, SortOrder};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Directories with identical trees
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateDirGroup {
+    pub dirs: Vec<PathBuf>,
+    /// Total size of the files in one copy
+    pub size: u64,
+    /// Number of files in one copy
+    pub files: usize,
+    pub fingerprint: Digest,
+}
+
+impl DuplicateDirGroup {
+    /// Bytes freed by keeping a single copy
+    pub fn wasted(&self) -> u64 {
+        self.size
+            .saturating_mul(self.dirs.len().saturating_sub(1) as u64)
+    }
+}
+
+/// Contents of a directory gathered while walking it
+struct DirSummary {
+    /// Name, kind (`f` or `d`) and hash of every counted entry
+    entries: Vec<(OsString, char, Digest)>,
+    size: u64,
+    files: usize,
+    /// False once any entry can't be matched against other directories
+    complete: bool,
+}
+
+impl Default for DirSummary {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            size: 0,
+            files: 0,
+            complete: true,
+        }
+    }
+}
+
+impl DirSummary {
+    fn fingerprint(&mut self) -> Digest {
+        self.entries.sort();
+        let mut hasher = HashAlgorithm::Xxh3.hasher();
+        for (name, kind, hash) in &self.entries {
+            hasher.update(name.as_encoded_bytes());
+            hasher.update(&[0, *kind as u8]);
+            hasher.update(hash.as_bytes());
+            hasher.update(&[0]);
+        }
+        hasher.finish()
+    }
+}
+
+/// Find directories under `roots` whose trees are identical
+///
+/// `groups` must be the duplicate groups found by scanning the same `roots`.
+/// Nested duplicates are collapsed into the highest matching ancestor: a
+/// group is left out when every directory in it sits inside another
+/// duplicate directory.
+pub fn find_duplicate_dirs(roots: &[PathBuf], groups: &[DuplicateGroup]) -> Vec<DuplicateDirGroup> {
+    find_duplicate_dirs_in(&LocalFs, roots, groups)
+}
+
+/// Like [`find_duplicate_dirs`], reading the trees from `fs`, which must be
+/// the filesystem the groups were scanned from
+pub fn find_duplicate_dirs_in(
+    fs: &dyn FileSystem,
+    roots: &[PathBuf],
+    groups: &[DuplicateGroup],
+) -> Vec<DuplicateDirGroup> {
+    let hashes: HashMap<&Path, Digest> = groups
+        .iter()
+        .flat_map(|g| g.files.iter().map(|f| (f.path.as_path(), g.hash)))
+        .collect();
+
+    // Fingerprint, size and file count of every directory that has one
+    let mut fingerprints: HashMap<PathBuf, (Digest, u64, usize)> = HashMap::new();
+
+    for root in roots {
+        if !fs
+            .metadata(root)
+            .is_ok_and(|m| m.file_type == FileType::Dir)
+        {
+            continue;
+        }
+        let mut summary = summarize(fs, root, &hashes, &mut fingerprints);
+        if summary.complete && summary.files > 0 {
+            let fingerprint = summary.fingerprint();
+            fingerprints.insert(root.clone(), (fingerprint, summary.size, summary.files));
+        }
+    }
+
+    let mut by_fingerprint: HashMap<Digest, DuplicateDirGroup> = HashMap::new();
+    for (path, (fingerprint, size, files)) in &fingerprints {
+        by_fingerprint
+            .entry(*fingerprint)
+            .or_insert_with(|| DuplicateDirGroup {
+                dirs: Vec::new(),
+                size: *size,
+                files: *files,
+                fingerprint: *fingerprint,
+            })
+            .dirs
+            .push(path.clone());
+    }
+    by_fingerprint.retain(|_, group| group.dirs.len() > 1);
+
+    let duplicated: HashSet<&Path> = by_fingerprint
+        .values()
+        .flat_map(|g| g.dirs.iter().map(PathBuf::as_path))
+        .collect();
+    let mut dir_groups: Vec<DuplicateDirGroup> = by_fingerprint
+        .values()
+        .filter(|group| {
+            group
+                .dirs
+                .iter()
+                .any(|dir| !dir.parent().is_some_and(|p| duplicated.contains(p)))
+        })
+        .cloned()
+        .collect();
+
+    for group in &mut dir_groups {
+        group.dirs.sort();
+    }
+    sort_dir_groups(&mut dir_groups, SortOrder::Wasted);
+    dir_groups
+}
+
+/// Gather the contents of `dir`, adding the fingerprint of every complete
+/// subdirectory with files to `fingerprints`
+///
+/// Children are summarized before their parent, so a directory is complete
+/// by the time its own fingerprint is taken.
+fn summarize(
+    fs: &dyn FileSystem,
+    dir: &Path,
+    hashes: &HashMap<&Path, Digest>,
+    fingerprints: &mut HashMap<PathBuf, (Digest, u64, usize)>,
+) -> DirSummary {
+    let mut summary = DirSummary::default();
+    let Ok(mut entries) = fs.read_dir(dir) else {
+        summary.complete = false;
+        return summary;
+    };
+    entries.sort();
+
+    for path in entries {
+        let Ok(metadata) = fs.symlink_metadata(&path) else {
+            // Something below this directory is unreadable
+            summary.complete = false;
+            continue;
+        };
+        let name = path.file_name().unwrap_or_default().to_os_string();
+
+        match metadata.file_type {
+            FileType::File if metadata.len > 0 => match hashes.get(path.as_path()) {
+                Some(hash) => {
+                    summary.entries.push((name, 'f', *hash));
+                    summary.size += metadata.len;
+                    summary.files += 1;
+                }
+                None => summary.complete = false,
+            },
+            FileType::Dir => {
+                let mut child = summarize(fs, &path, hashes, fingerprints);
+                // A directory without files doesn't count, but an incomplete
+                // one makes its parent incomplete too
+                if child.complete && child.files > 0 {
+                    let fingerprint = child.fingerprint();
+                    summary.entries.push((name, 'd', fingerprint));
+                    summary.size += child.size;
+                    summary.files += child.files;
+                    fingerprints.insert(path, (fingerprint, child.size, child.files));
+                } else if !child.complete {
+                    summary.complete = false;
+                }
+            }
+            // Empty files, symlinks and special files are ignored
+            _ => {}
+        }
+    }
+    summary
+}
+
+/// Sort directory groups the same way [`crate::sort_groups`] sorts file groups
+pub fn sort_dir_groups(groups: &mut [DuplicateDirGroup], order: SortOrder) {
+    groups.sort_by(|a, b| {
+        let primary = match order {
+            SortOrder::Wasted => b.wasted().cmp(&a.wasted()),
+            SortOrder::Size => b.size.cmp(&a.size),
+            SortOrder::Count => b.dirs.len().cmp(&a.dirs.len()),
+            SortOrder::Path => std::cmp::Ordering::Equal,
+        };
+        primary
+            .then_with(|| a.dirs.first().cmp(&b.dirs.first()))
+            .then_with(|| a.fingerprint.cmp(&b.fingerprint))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+    use crate::test_util::write_file;
+    use crate::vfs::MemoryFs;
+    use tempfile::TempDir;
+
+    fn duplicate_dirs(root: &Path) -> Vec<DuplicateDirGroup> {
+        let result = Scanner::new().root(root).scan().unwrap();
+        find_duplicate_dirs(&[root.to_path_buf()], &result.groups)
+    }
+
+    #[test]
+    fn test_identical_trees_collapse_into_top_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        for copy in ["project", "project_backup"] {
+            write_file(temp_dir.path(), &format!("{copy}/main.rs"), b"fn main() {}");
+            write_file(
+                temp_dir.path(),
+                &format!("{copy}/src/lib.rs"),
+                b"pub mod a;",
+            );
+            write_file(
+                temp_dir.path(),
+                &format!("{copy}/src/a.rs"),
+                b"pub fn a() {}",
+            );
+        }
+        // Same files, but one is renamed, so the trees differ
+        write_file(temp_dir.path(), "renamed/main.rs", b"fn main() {}");
+        write_file(temp_dir.path(), "renamed/src/lib.rs", b"pub mod a;");
+        write_file(temp_dir.path(), "renamed/src/b.rs", b"pub fn a() {}");
+
+        let groups = duplicate_dirs(temp_dir.path());
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].dirs,
+            vec![
+                temp_dir.path().join("project"),
+                temp_dir.path().join("project_backup")
+            ]
+        );
+        assert_eq!(groups[0].files, 3);
+    }
+
+    #[test]
+    fn test_nested_copy_outside_duplicate_parent_is_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        for copy in ["a", "b"] {
+            write_file(temp_dir.path(), &format!("{copy}/photos/1.jpg"), b"one");
+            write_file(temp_dir.path(), &format!("{copy}/notes.txt"), b"notes");
+        }
+        write_file(temp_dir.path(), "c/photos/1.jpg", b"one");
+        write_file(temp_dir.path(), "c/unique.txt", b"only here");
+
+        let groups = duplicate_dirs(temp_dir.path());
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].dirs.len(), 2); // a and b
+        assert_eq!(groups[1].dirs.len(), 3); // the three photos directories
+        assert!(groups[1].dirs.contains(&temp_dir.path().join("c/photos")));
+    }
+
+    #[test]
+    fn test_trees_are_read_from_the_scanned_filesystem() {
+        let fs = MemoryFs::new()
+            .file("/tree/a/1.txt", "one")
+            .file("/tree/a/sub/2.txt", "two")
+            .file("/tree/b/1.txt", "one")
+            .file("/tree/b/sub/2.txt", "two")
+            .file("/tree/c/1.txt", "one");
+        let result = Scanner::new()
+            .filesystem(fs.clone())
+            .root("/tree")
+            .scan()
+            .unwrap();
+
+        let groups = find_duplicate_dirs_in(&fs, &[PathBuf::from("/tree")], &result.groups);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].dirs, [Path::new("/tree/a"), Path::new("/tree/b")]);
+        assert_eq!((groups[0].files, groups[0].size), (2, 6));
+    }
+}
@@ -0,0 +1,93 @@
+//! Errors reported by scans
+//!
+//! Scanning and comparing return [`Error`] when they can't go on. Problems
+//! with single files don't stop a scan: [`crate::scanner::Scanner`] collects
+//! them into [`crate::scanner::ScanResult::warnings`], one [`Error`] per file
+//! or directory skipped.
+//!
+//! The modules that change files or keep state between runs (actions,
+//! journal, plan, session, index and watch) return [`anyhow::Result`]: their
+//! failures are meant to be shown to a user, not matched on.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The file or directory can't be read with the current permissions
+    #[error("Permission denied: {}", .0.display())]
+    PermissionDenied(PathBuf),
+    /// The file or directory doesn't exist, typically because it was removed
+    /// while the scan ran
+    #[error("Vanished: {}", .0.display())]
+    Vanished(PathBuf),
+    /// A path given to scan doesn't exist
+    #[error("No such file or directory: {}", .0.display())]
+    RootNotFound(PathBuf),
+    /// A followed symlink leads back to one of its own parent directories
+    #[error("Symlink loop: {}", .0.display())]
+    SymlinkLoop(PathBuf),
+    /// Any other I/O failure
+    #[error("I/O error on {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// The operation was stopped through its
+    /// [`crate::cancel::CancellationToken`]
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Classify an I/O error that happened while accessing `path`
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        let path = path.into();
+        match source.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(path),
+            io::ErrorKind::NotFound => Self::Vanished(path),
+            _ => Self::Io { path, source },
+        }
+    }
+
+    /// The file or directory the error is about, if any
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::PermissionDenied(path)
+            | Self::Vanished(path)
+            | Self::RootNotFound(path)
+            | Self::SymlinkLoop(path) => Some(path),
+            Self::Io { path, .. } => Some(path),
+            Self::Cancelled => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_errors_are_classified() {
+        let path = Path::new("some/file");
+        let error = |kind| Error::io(path, io::Error::from(kind));
+
+        assert!(matches!(
+            error(io::ErrorKind::PermissionDenied),
+            Error::PermissionDenied(_)
+        ));
+        assert!(matches!(error(io::ErrorKind::NotFound), Error::Vanished(_)));
+        assert!(matches!(
+            error(io::ErrorKind::InvalidData),
+            Error::Io { .. }
+        ));
+        assert_eq!(error(io::ErrorKind::NotFound).path(), Some(path));
+        assert_eq!(
+            error(io::ErrorKind::PermissionDenied).to_string(),
+            "Permission denied: some/file"
+        );
+    }
+}
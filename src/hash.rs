@@ -0,0 +1,303 @@
+//! Content hash algorithms
+//!
+//! XXH3-64 is the fast default for finding duplicates. XXH3-128 lowers the
+//! chance of a collision further, and SHA-256 and BLAKE3 are cryptographic
+//! digests for archives that need them. Every algorithm is a
+//! [`ContentHasher`]; [`HashAlgorithm`] names the one to use.
+//!
+//! Digests are kept as [`Digest`] values: the raw bytes inline, tagged with
+//! their algorithm, so digests of different algorithms never compare equal.
+//! Hex is only produced for display and for the files written to disk.
+
+use crate::cancel::CancellationToken;
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::Digest as _;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Incrementally hashes file content
+pub trait ContentHasher {
+    fn update(&mut self, bytes: &[u8]);
+
+    fn finish(self: Box<Self>) -> Digest;
+}
+
+/// A supported hash algorithm
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgorithm {
+    /// XXH3, 64 bits (fast, not cryptographic)
+    #[default]
+    Xxh3,
+    /// XXH3, 128 bits (fast, not cryptographic)
+    #[value(name = "xxh3-128")]
+    #[serde(rename = "xxh3-128")]
+    Xxh3_128,
+    /// SHA-256
+    Sha256,
+    /// BLAKE3, 256 bits
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// A fresh hasher for this algorithm
+    pub fn hasher(self) -> Box<dyn ContentHasher> {
+        match self {
+            HashAlgorithm::Xxh3 => Box::new(Xxh3_64Hasher(Xxh3::new())),
+            HashAlgorithm::Xxh3_128 => Box::new(Xxh3_128Hasher(Xxh3::new())),
+            HashAlgorithm::Sha256 => Box::new(Sha256Hasher(sha2::Sha256::new())),
+            HashAlgorithm::Blake3 => Box::new(Blake3Hasher(Box::new(blake3::Hasher::new()))),
+        }
+    }
+
+    /// Length of this algorithm's digests in bytes
+    pub fn digest_len(self) -> usize {
+        match self {
+            HashAlgorithm::Xxh3 => 8,
+            HashAlgorithm::Xxh3_128 => 16,
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 32,
+        }
+    }
+
+    /// Whether `self` is the default, for skipping it when serializing
+    pub fn is_default(&self) -> bool {
+        *self == HashAlgorithm::default()
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HashAlgorithm::Xxh3 => "xxh3",
+            HashAlgorithm::Xxh3_128 => "xxh3-128",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Longest digest of any algorithm, in bytes
+const MAX_DIGEST_LEN: usize = 32;
+
+/// A content digest, stored inline
+///
+/// Copying one is cheap and never allocates. Ordering compares the algorithm
+/// first, then the bytes, which for one algorithm matches the order of the hex
+/// strings.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Digest {
+    algorithm: HashAlgorithm,
+    // Only the first `algorithm.digest_len()` bytes are used; the rest are zero
+    bytes: [u8; MAX_DIGEST_LEN],
+}
+
+impl Digest {
+    /// A digest of `algorithm` from its raw bytes
+    ///
+    /// Panics if `bytes` doesn't have the algorithm's digest length.
+    pub fn new(algorithm: HashAlgorithm, bytes: &[u8]) -> Self {
+        assert_eq!(
+            bytes.len(),
+            algorithm.digest_len(),
+            "wrong digest length for {}",
+            algorithm
+        );
+        let mut digest = Self {
+            algorithm,
+            bytes: [0; MAX_DIGEST_LEN],
+        };
+        digest.bytes[..bytes.len()].copy_from_slice(bytes);
+        digest
+    }
+
+    /// Parse a digest of `algorithm` from hex, as written by [`Digest::to_hex`]
+    pub fn from_hex(algorithm: HashAlgorithm, hex: &str) -> Option<Self> {
+        if !hex.is_ascii() || hex.len() != algorithm.digest_len() * 2 {
+            return None;
+        }
+        let mut bytes = [0; MAX_DIGEST_LEN];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+        }
+        Some(Self { algorithm, bytes })
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.algorithm.digest_len()]
+    }
+
+    /// The digest as lowercase hex
+    pub fn to_hex(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.as_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self)
+    }
+}
+
+/// Serialized as hex, like the digests in the journal and plan files
+impl Serialize for Digest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct Xxh3_64Hasher(Xxh3);
+
+impl ContentHasher for Xxh3_64Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new(HashAlgorithm::Xxh3, &self.0.digest().to_be_bytes())
+    }
+}
+
+struct Xxh3_128Hasher(Xxh3);
+
+impl ContentHasher for Xxh3_128Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new(HashAlgorithm::Xxh3_128, &self.0.digest128().to_be_bytes())
+    }
+}
+
+struct Sha256Hasher(sha2::Sha256);
+
+impl ContentHasher for Sha256Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        sha2::Digest::update(&mut self.0, bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new(HashAlgorithm::Sha256, &self.0.finalize())
+    }
+}
+
+// Boxed, as the hasher state is large
+struct Blake3Hasher(Box<blake3::Hasher>);
+
+impl ContentHasher for Blake3Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Digest {
+        Digest::new(HashAlgorithm::Blake3, self.0.finalize().as_bytes())
+    }
+}
+
+/// Hash everything `reader` yields with `hasher`
+pub fn hash_reader(
+    reader: &mut impl Read,
+    hasher: Box<dyn ContentHasher>,
+) -> std::io::Result<Digest> {
+    hash_reader_observed(reader, hasher, &CancellationToken::new(), |_| {})
+}
+
+/// Like [`hash_reader`], calling `on_read` with the size of every chunk read
+///
+/// Fails with [`std::io::ErrorKind::Interrupted`] once `cancel` is cancelled.
+pub(crate) fn hash_reader_observed(
+    reader: &mut impl Read,
+    mut hasher: Box<dyn ContentHasher>,
+    cancel: &CancellationToken,
+    mut on_read: impl FnMut(u64),
+) -> std::io::Result<Digest> {
+    let mut buffer = [0; 8192];
+    loop {
+        if cancel.is_cancelled() {
+            return Err(std::io::ErrorKind::Interrupted.into());
+        }
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+        on_read(bytes_read as u64);
+    }
+    Ok(hasher.finish())
+}
+
+/// Hash the file at `path` with `algorithm`
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> std::io::Result<Digest> {
+    hash_reader(&mut File::open(path)?, algorithm.hasher())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_digests() {
+        let digest = |algorithm: HashAlgorithm| {
+            hash_reader(&mut &b"abc"[..], algorithm.hasher())
+                .unwrap()
+                .to_hex()
+        };
+
+        assert_eq!(
+            digest(HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            digest(HashAlgorithm::Blake3),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(digest(HashAlgorithm::Xxh3).len(), 16);
+        assert_eq!(digest(HashAlgorithm::Xxh3_128).len(), 32);
+    }
+
+    #[test]
+    fn test_digest_hex_round_trip() {
+        let digest = hash_reader(&mut &b"abc"[..], HashAlgorithm::Xxh3.hasher()).unwrap();
+        let hex = digest.to_hex();
+
+        assert_eq!(Digest::from_hex(HashAlgorithm::Xxh3, &hex), Some(digest));
+        assert_eq!(Digest::from_hex(HashAlgorithm::Xxh3_128, &hex), None);
+        assert_eq!(
+            Digest::from_hex(HashAlgorithm::Xxh3, "not hex at all!!"),
+            None
+        );
+        assert_eq!(
+            serde_json::to_string(&digest).unwrap(),
+            format!("\"{}\"", hex)
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! Persistent content index
+//!
+//! Stores the size, modification time and hash of every file under a set of
+//! roots in a SQLite database, so "do we already have this content?" can be
+//! answered without rescanning. `update` only rehashes files whose size or
+//! modification time changed, and `query` only hashes the queried file when
+//! the index holds a file of the same size.
+//!
+//! An index uses one hash algorithm, chosen when it is created.
+
+use crate::error::Error;
+use crate::hash::HashAlgorithm;
+use crate::scanner::Scanner;
+use crate::{bytes_to_path, data_dir, modification_time, path_to_bytes, FileInfo};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// What an indexing run changed
+#[derive(Debug, Default)]
+pub struct IndexStats {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    /// Files that couldn't be hashed and were left out
+    pub failed: usize,
+    /// Roots, directories and files the walk couldn't read; whatever the
+    /// index holds below them is kept as it was
+    pub warnings: Vec<Error>,
+}
+
+pub struct Index {
+    conn: Connection,
+    algorithm: HashAlgorithm,
+}
+
+impl Index {
+    /// Open the index at `path`, creating it with the default algorithm if
+    /// needed
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::open_inner(path, None)
+    }
+
+    /// Open the index at `path`, which must use `algorithm`, creating it if
+    /// needed
+    pub fn open_with_algorithm(path: &Path, algorithm: HashAlgorithm) -> anyhow::Result<Self> {
+        Self::open_inner(path, Some(algorithm))
+    }
+
+    fn open_inner(path: &Path, requested: Option<HashAlgorithm>) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS roots (path BLOB PRIMARY KEY);
+             CREATE TABLE IF NOT EXISTS files (
+                 path BLOB PRIMARY KEY,
+                 size INTEGER NOT NULL,
+                 mtime INTEGER NOT NULL,
+                 hash TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS files_by_content ON files (size, hash);
+             CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+        )?;
+
+        let stored: Option<String> = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'algorithm'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let algorithm = match stored {
+            Some(name) => {
+                let stored = <HashAlgorithm as clap::ValueEnum>::from_str(&name, false)
+                    .map_err(|_| anyhow::anyhow!("Unknown hash algorithm {} in index", name))?;
+                if let Some(requested) = requested.filter(|&requested| requested != stored) {
+                    anyhow::bail!(
+                        "Index {} uses {} hashes; build a new index to use {}",
+                        path.display(),
+                        stored,
+                        requested
+                    );
+                }
+                stored
+            }
+            None => {
+                let algorithm = requested.unwrap_or_default();
+                conn.execute(
+                    "INSERT INTO meta (key, value) VALUES ('algorithm', ?1)",
+                    params![algorithm.to_string()],
+                )?;
+                algorithm
+            }
+        };
+
+        Ok(Self { conn, algorithm })
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Default index location inside the user's data directory
+    pub fn default_path() -> PathBuf {
+        data_dir().join("index.sqlite3")
+    }
+
+    /// Roots added with [`Index::build`]
+    pub fn roots(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut stmt = self.conn.prepare("SELECT path FROM roots ORDER BY path")?;
+        let roots = stmt
+            .query_map([], |row| row.get::<_, Vec<u8>>(0))?
+            .map(|bytes| Ok(bytes_to_path(bytes?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(roots)
+    }
+
+    /// Add `root` to the index and hash every file under it
+    pub fn build(&mut self, root: &Path) -> anyhow::Result<IndexStats> {
+        let root = std::path::absolute(root)?;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO roots (path) VALUES (?1)",
+            params![path_to_bytes(&root)],
+        )?;
+        self.refresh(&root, true)
+    }
+
+    /// Bring `root` up to date, rehashing only files that changed
+    ///
+    /// A root that is missing or unreadable, say because its disk isn't
+    /// mounted, is reported in [`IndexStats::warnings`] and its entries are
+    /// left alone rather than removed.
+    pub fn update(&mut self, root: &Path) -> anyhow::Result<IndexStats> {
+        let root = std::path::absolute(root)?;
+        self.refresh(&root, false)
+    }
+
+    fn refresh(&mut self, root: &Path, rehash: bool) -> anyhow::Result<IndexStats> {
+        let mut stats = IndexStats::default();
+        let mut files = Vec::new();
+        Scanner::new().root(root).walk(
+            |path, size| files.push(FileInfo::new(path.to_path_buf(), size)),
+            &mut stats.warnings,
+        );
+
+        let tx = self.conn.transaction()?;
+
+        // Everything indexed under the root, to find files that are gone
+        let mut indexed: HashMap<PathBuf, (u64, u64)> = HashMap::new();
+        {
+            let mut stmt = tx.prepare("SELECT path, size, mtime FROM files")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    bytes_to_path(row.get(0)?),
+                    row.get::<_, i64>(1)? as u64,
+                    row.get::<_, i64>(2)? as u64,
+                ))
+            })?;
+            for row in rows {
+                let (path, size, mtime) = row?;
+                // Files below what couldn't be read may well still exist
+                let unread = stats
+                    .warnings
+                    .iter()
+                    .filter_map(Error::path)
+                    .any(|unread| path.starts_with(unread));
+                if path.starts_with(root) && !unread {
+                    indexed.insert(path, (size, mtime));
+                }
+            }
+        }
+
+        for mut file in files {
+            let mtime = modification_time(&file.path).unwrap_or(0);
+            let previous = indexed.remove(&file.path);
+            if !rehash && previous == Some((file.size, mtime)) {
+                stats.unchanged += 1;
+                continue;
+            }
+
+            let hash = match file.calculate_hash_with(self.algorithm) {
+                Ok(hash) => hash.to_hex(),
+                Err(_) => {
+                    stats.failed += 1;
+                    // Don't keep answering queries with the old content
+                    if previous.is_some() {
+                        indexed.insert(file.path.clone(), (0, 0));
+                    }
+                    continue;
+                }
+            };
+            tx.execute(
+                "INSERT OR REPLACE INTO files (path, size, mtime, hash) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    path_to_bytes(&file.path),
+                    file.size as i64,
+                    mtime as i64,
+                    hash
+                ],
+            )?;
+            if previous.is_some() {
+                stats.updated += 1;
+            } else {
+                stats.added += 1;
+            }
+        }
+
+        for path in indexed.keys() {
+            tx.execute(
+                "DELETE FROM files WHERE path = ?1",
+                params![path_to_bytes(path)],
+            )?;
+            stats.removed += 1;
+        }
+
+        tx.commit()?;
+        Ok(stats)
+    }
+
+    /// Indexed files with the same content as `path`, other than `path` itself
+    pub fn query(&self, path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let path = std::path::absolute(path)?;
+        let size = std::fs::metadata(&path)?.len();
+
+        let same_size: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT 1 FROM files WHERE size = ?1 LIMIT 1",
+                params![size as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if same_size.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let mut file = FileInfo::new(path.clone(), size);
+        let hash = file.calculate_hash_with(self.algorithm)?;
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path FROM files WHERE size = ?1 AND hash = ?2 ORDER BY path")?;
+        let matches = stmt
+            .query_map(params![size as i64, hash.to_hex()], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })?
+            .map(|bytes| Ok(bytes_to_path(bytes?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(matches.into_iter().filter(|p| *p != path).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_build_update_and_query() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        fs::create_dir_all(&library).unwrap();
+        fs::write(library.join("a.txt"), "stored content").unwrap();
+        fs::write(library.join("b.txt"), "other content").unwrap();
+        fs::write(library.join("c.txt"), "removed later").unwrap();
+
+        let mut index = Index::open(&temp_dir.path().join("index.sqlite3")).unwrap();
+        let stats = index.build(&library).unwrap();
+        assert_eq!(stats.added, 3);
+        assert_eq!(index.roots().unwrap(), vec![library.clone()]);
+
+        fs::remove_file(library.join("c.txt")).unwrap();
+        fs::write(library.join("d.txt"), "new file").unwrap();
+        let stats = index.update(&library).unwrap();
+        assert_eq!(
+            (
+                stats.added,
+                stats.updated,
+                stats.unchanged,
+                stats.removed,
+                stats.failed
+            ),
+            (1, 0, 2, 1, 0)
+        );
+        assert!(stats.warnings.is_empty());
+
+        // An unmounted library must not empty the index
+        let unmounted = temp_dir.path().join("unmounted");
+        fs::rename(&library, &unmounted).unwrap();
+        let stats = index.update(&library).unwrap();
+        assert_eq!((stats.removed, stats.warnings.len()), (0, 1));
+        assert!(matches!(stats.warnings[0], Error::RootNotFound(_)));
+        fs::rename(&unmounted, &library).unwrap();
+
+        assert!(Index::open_with_algorithm(
+            &temp_dir.path().join("index.sqlite3"),
+            HashAlgorithm::Sha256
+        )
+        .is_err());
+
+        let incoming = temp_dir.path().join("incoming.txt");
+        fs::write(&incoming, "stored content").unwrap();
+        assert_eq!(index.query(&incoming).unwrap(), vec![library.join("a.txt")]);
+
+        let unknown = temp_dir.path().join("unknown.txt");
+        fs::write(&unknown, "stored CONTENT").unwrap();
+        assert!(index.query(&unknown).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+//! Append-only journal of destructive operations
+//!
+//! Every deletion, link replacement or move is written as one JSON line, so
+//! the changes can be reviewed afterwards and reversed with `undo`.
+
+use crate::actions::{move_file, replace_with, ActionKind};
+use crate::hash::HashAlgorithm;
+use crate::{data_dir, FileInfo};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A single recorded operation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub action: ActionKind,
+    /// The duplicate that was changed
+    pub path: PathBuf,
+    /// The copy that was kept
+    pub keeper: PathBuf,
+    pub size: u64,
+    pub hash: String,
+    /// Algorithm of `hash`
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_default")]
+    pub algorithm: HashAlgorithm,
+    /// Where the file was moved to, for move operations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<PathBuf>,
+    /// Set when this entry records the reversal of an earlier `action` on `path`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub undo: bool,
+}
+
+impl JournalEntry {
+    pub fn new(
+        action: ActionKind,
+        file: &FileInfo,
+        keeper: &FileInfo,
+        destination: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            timestamp: now(),
+            action,
+            path: absolute(&file.path),
+            keeper: absolute(&keeper.path),
+            size: file.size,
+            hash: file.hash.map(|h| h.to_hex()).unwrap_or_default(),
+            algorithm: file.hash.map(|h| h.algorithm()).unwrap_or_default(),
+            destination: destination.as_deref().map(absolute),
+            undo: false,
+        }
+    }
+
+    /// An entry recording that this operation was reversed
+    pub fn reversed(&self) -> Self {
+        Self {
+            timestamp: now(),
+            undo: true,
+            ..self.clone()
+        }
+    }
+}
+
+/// An open journal file that entries are appended to
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    /// Open (or create) the journal at `path` for appending
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Default journal location inside the user's data directory
+    pub fn default_path() -> PathBuf {
+        data_dir().join("journal.jsonl")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append an entry and flush it to disk before returning
+    pub fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        let line = serde_json::to_string(entry)?;
+        writeln!(self.file, "{}", line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Read every entry of the journal at `path`, oldest first
+    pub fn read_entries(path: &Path) -> anyhow::Result<Vec<JournalEntry>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut entries = Vec::new();
+        for (line_no, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).map_err(|e| {
+                anyhow::anyhow!(
+                    "{}:{}: invalid journal entry: {}",
+                    path.display(),
+                    line_no + 1,
+                    e
+                )
+            })?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+/// Operations that have not been reversed yet, most recent first
+///
+/// A deletion is left out once a later operation touched the same path,
+/// since the path was recreated in between.
+pub fn pending_operations(entries: &[JournalEntry]) -> Vec<&JournalEntry> {
+    let mut pending: Vec<&JournalEntry> = Vec::new();
+    for entry in entries {
+        if entry.undo {
+            if let Some(pos) = pending
+                .iter()
+                .rposition(|p| p.path == entry.path && p.action == entry.action)
+            {
+                pending.remove(pos);
+            }
+        } else {
+            pending.retain(|p| p.path != entry.path || p.action != ActionKind::Delete);
+            pending.push(entry);
+        }
+    }
+    pending.reverse();
+    pending
+}
+
+/// Result of reversing a journal entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UndoOutcome {
+    /// The original file is back in place
+    Restored,
+    /// The file was deleted; copying `keeper` back to the original path
+    /// recreates it. `keeper_intact` tells whether the keeper still has the
+    /// recorded content.
+    CopyFromKeeper {
+        keeper: PathBuf,
+        keeper_intact: bool,
+    },
+    /// The file was deleted, but something is back at its path, so there is
+    /// nothing left to undo
+    Recreated,
+}
+
+/// Reverse a single recorded operation
+pub fn undo_entry(entry: &JournalEntry) -> anyhow::Result<UndoOutcome> {
+    match entry.action {
+        ActionKind::Delete => {
+            if fs::symlink_metadata(&entry.path).is_ok() {
+                return Ok(UndoOutcome::Recreated);
+            }
+            let mut keeper = FileInfo::new(entry.keeper.clone(), entry.size);
+            let keeper_intact = fs::metadata(&keeper.path).is_ok_and(|m| m.len() == entry.size)
+                && keeper
+                    .calculate_hash_with(entry.algorithm)
+                    .is_ok_and(|h| entry.hash.is_empty() || h.to_hex() == entry.hash);
+            Ok(UndoOutcome::CopyFromKeeper {
+                keeper: entry.keeper.clone(),
+                keeper_intact,
+            })
+        }
+        ActionKind::Move => {
+            let destination = entry
+                .destination
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Journal entry has no move destination"))?;
+            if entry.path.exists() {
+                anyhow::bail!("{} already exists", entry.path.display());
+            }
+            move_file(destination, &entry.path)?;
+            Ok(UndoOutcome::Restored)
+        }
+        ActionKind::Hardlink | ActionKind::Symlink => {
+            let metadata = fs::symlink_metadata(&entry.path)
+                .map_err(|_| anyhow::anyhow!("{} no longer exists", entry.path.display()))?;
+            if entry.action == ActionKind::Symlink && !metadata.file_type().is_symlink() {
+                anyhow::bail!("{} is no longer a symlink", entry.path.display());
+            }
+            if fs::metadata(&entry.keeper).map(|m| m.len()).ok() != Some(entry.size) {
+                anyhow::bail!("Keeper {} changed or is missing", entry.keeper.display());
+            }
+            // Give the path its own copy of the content again
+            replace_with(&entry.path, |tmp| fs::copy(&entry.keeper, tmp).map(|_| ()))?;
+            Ok(UndoOutcome::Restored)
+        }
+    }
+}
+
+/// Journal paths are stored absolute so `undo` works from any directory
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::{apply_action, Action};
+    use crate::test_util::write_file;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let keeper = write_file(temp_dir.path(), "keep.txt", b"content");
+        let dup = write_file(temp_dir.path(), "dup.txt", b"content");
+        let entry = JournalEntry::new(ActionKind::Delete, &dup, &keeper, None);
+
+        let journal_path = temp_dir.path().join("journal.jsonl");
+        let mut journal = Journal::open(&journal_path).unwrap();
+        journal.record(&entry).unwrap();
+        journal.record(&entry.reversed()).unwrap();
+
+        let entries = Journal::read_entries(&journal_path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], entry);
+        assert!(entries[1].undo);
+        assert!(pending_operations(&entries).is_empty());
+    }
+
+    #[test]
+    fn test_undo_move_and_symlink() {
+        let temp_dir = TempDir::new().unwrap();
+        let keeper = write_file(temp_dir.path(), "keep.txt", b"content");
+        let moved = write_file(temp_dir.path(), "moved.txt", b"content");
+        let linked = write_file(temp_dir.path(), "linked.txt", b"content");
+
+        let move_action = Action::Move(temp_dir.path().join("trash"));
+        let move_entry = apply_action(&move_action, &moved, &keeper).unwrap();
+        let link_entry = apply_action(&Action::Symlink, &linked, &keeper).unwrap();
+        assert!(!moved.path.exists());
+        assert!(fs::symlink_metadata(&linked.path).unwrap().is_symlink());
+
+        assert_eq!(undo_entry(&move_entry).unwrap(), UndoOutcome::Restored);
+        assert_eq!(undo_entry(&link_entry).unwrap(), UndoOutcome::Restored);
+
+        assert_eq!(fs::read(&moved.path).unwrap(), b"content");
+        assert!(fs::symlink_metadata(&linked.path).unwrap().is_file());
+    }
+
+    #[test]
+    fn test_undo_delete_points_to_keeper() {
+        let temp_dir = TempDir::new().unwrap();
+        let keeper = write_file(temp_dir.path(), "keep.txt", b"content");
+        let dup = write_file(temp_dir.path(), "dup.txt", b"content");
+
+        let entry = apply_action(&Action::Delete, &dup, &keeper).unwrap();
+
+        assert_eq!(
+            undo_entry(&entry).unwrap(),
+            UndoOutcome::CopyFromKeeper {
+                keeper: keeper.path.clone(),
+                keeper_intact: true,
+            }
+        );
+
+        // Copied back by hand, then deleted again by a later run
+        fs::copy(&keeper.path, &dup.path).unwrap();
+        assert_eq!(undo_entry(&entry).unwrap(), UndoOutcome::Recreated);
+        let again = apply_action(&Action::Delete, &dup, &keeper).unwrap();
+        let entries = [entry, again.clone()];
+        assert_eq!(pending_operations(&entries), vec![&again]);
+    }
+}
@@ -3,20 +3,50 @@
 //! This library provides functionality to find duplicate files using xxHash
 //! with size-based pre-filtering for efficiency. Supports both report-only
 //! and interactive duplicate resolution modes.
-
+//!
+//! Start with [`scanner::Scanner`] to run a scan. The library never prints;
+//! what a scan has to skip is handed back as [`error::Error`] values.
+
+pub mod actions;
+mod bounded;
+pub mod cancel;
+pub mod compare;
+pub mod dirs;
+pub mod error;
+pub mod hash;
+pub mod index;
+pub mod journal;
+pub mod overlap;
+pub mod plan;
+pub mod preview;
+pub mod progress;
+pub mod rules;
+pub mod scanner;
+pub mod script;
+pub mod session;
+pub mod stream;
+#[cfg(test)]
+mod test_util;
+pub mod vfs;
+pub mod watch;
+
+use cancel::CancellationToken;
+use error::{Error, Result};
+use hash::{hash_reader_observed, Digest, HashAlgorithm};
+use progress::{NoProgress, ProgressEvent, ProgressObserver};
+use scanner::Scanner;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
-use xxhash_rust::xxh3::Xxh3;
+use std::time::UNIX_EPOCH;
+use vfs::{FileSystem, FileType, LocalFs};
 
 /// Represents a file with its metadata
 #[derive(Debug, Clone)]
 pub struct FileInfo {
     pub path: PathBuf,
     pub size: u64,
-    pub hash: Option<String>,
+    pub hash: Option<Digest>,
 }
 
 impl FileInfo {
@@ -28,133 +58,290 @@ impl FileInfo {
         }
     }
 
-    /// Calculate xxHash (XXH3) of the file
-    pub fn calculate_hash(&mut self) -> anyhow::Result<&str> {
-        if self.hash.is_some() {
-            return Ok(self.hash.as_ref().unwrap());
-        }
+    /// Calculate the hash of the file with the algorithm of its cached hash,
+    /// or XXH3 if it has none
+    pub fn calculate_hash(&mut self) -> Result<Digest> {
+        let algorithm = self.hash.map(|h| h.algorithm()).unwrap_or_default();
+        self.calculate_hash_with(algorithm)
+    }
 
-        let mut file = match File::open(&self.path) {
-            Ok(f) => f,
-            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
-                return Err(anyhow::anyhow!(
-                    "Permission denied: {}",
-                    self.path.display()
-                ));
-            }
-            Err(e) => return Err(e.into()),
-        };
-        let mut hasher = Xxh3::new();
-        let mut buffer = [0; 8192];
+    /// Calculate the hash of the file with `algorithm`
+    ///
+    /// The result is cached; asking for a different algorithm rehashes.
+    pub fn calculate_hash_with(&mut self, algorithm: HashAlgorithm) -> Result<Digest> {
+        self.calculate_hash_in(&LocalFs, algorithm)
+    }
 
-        loop {
-            let bytes_read = file.read(&mut buffer)?;
-            if bytes_read == 0 {
-                break;
-            }
-            hasher.update(&buffer[..bytes_read]);
+    /// Like [`FileInfo::calculate_hash_with`], reading the file from `fs`
+    pub fn calculate_hash_in(
+        &mut self,
+        fs: &dyn FileSystem,
+        algorithm: HashAlgorithm,
+    ) -> Result<Digest> {
+        self.calculate_hash_observed(fs, algorithm, &CancellationToken::new(), |_| {})
+    }
+
+    /// Like [`FileInfo::calculate_hash_in`], calling `on_read` with the size
+    /// of every chunk read and stopping once `cancel` is cancelled
+    fn calculate_hash_observed(
+        &mut self,
+        fs: &dyn FileSystem,
+        algorithm: HashAlgorithm,
+        cancel: &CancellationToken,
+        on_read: impl FnMut(u64),
+    ) -> Result<Digest> {
+        if let Some(hash) = self.hash.filter(|h| h.algorithm() == algorithm) {
+            return Ok(hash);
         }
 
-        let hash = format!("{:016x}", hasher.digest());
+        let mut file = fs.open(&self.path).map_err(|e| Error::io(&self.path, e))?;
+        let hash =
+            hash_reader_observed(&mut file, algorithm.hasher(), cancel, on_read).map_err(|e| {
+                if cancel.is_cancelled() {
+                    Error::Cancelled
+                } else {
+                    Error::io(&self.path, e)
+                }
+            })?;
         self.hash = Some(hash);
-        Ok(self.hash.as_ref().unwrap())
+        Ok(hash)
     }
 }
 
 /// Recursively collect files and group them by size
+///
+/// Whatever can't be read is added to `warnings`.
 pub fn collect_files(
     path: &Path,
     files_by_size: &mut HashMap<u64, Vec<FileInfo>>,
     total_files: &mut usize,
-    verbose: bool,
-) -> anyhow::Result<()> {
-    if path.is_file() {
-        if let Ok(metadata) = path.metadata() {
-            let size = metadata.len();
-            if size > 0 {
-                // Skip empty files
-                let file_info = FileInfo::new(path.to_path_buf(), size);
-                files_by_size.entry(size).or_default().push(file_info);
-                *total_files += 1;
+    warnings: &mut Vec<Error>,
+) -> Result<()> {
+    collect_files_into(
+        path,
+        files_by_size,
+        total_files,
+        &CancellationToken::new(),
+        &mut NoProgress,
+        warnings,
+    );
+    Ok(())
+}
+
+/// Like [`collect_files`], reporting every file found to `progress`
+///
+/// Empty files, symlinks and anything unreadable are skipped silently; use
+/// [`scanner::Scanner`] to learn what was skipped.
+pub fn collect_files_with_progress(
+    path: &Path,
+    files_by_size: &mut HashMap<u64, Vec<FileInfo>>,
+    total_files: &mut usize,
+    progress: &mut dyn ProgressObserver,
+) -> Result<()> {
+    collect_files_into(
+        path,
+        files_by_size,
+        total_files,
+        &CancellationToken::new(),
+        progress,
+        &mut Vec::new(),
+    );
+    Ok(())
+}
+
+/// Like [`collect_files_with_progress`], adding whatever can't be read to
+/// `warnings` and stopping early once `cancel` is cancelled
+pub(crate) fn collect_files_into(
+    path: &Path,
+    files_by_size: &mut HashMap<u64, Vec<FileInfo>>,
+    total_files: &mut usize,
+    cancel: &CancellationToken,
+    progress: &mut dyn ProgressObserver,
+    warnings: &mut Vec<Error>,
+) {
+    visit_files(
+        &LocalFs,
+        path,
+        false,
+        cancel,
+        |file, size| {
+            // Skip empty files
+            if size == 0 {
+                return;
+            }
+            progress.on_progress(ProgressEvent::FileFound { size });
+            files_by_size
+                .entry(size)
+                .or_default()
+                .push(FileInfo::new(file.to_path_buf(), size));
+            *total_files += 1;
+        },
+        |e| warnings.push(e),
+    );
+}
 
-                if verbose {
-                    println!("  Found file: {} ({} bytes)", path.display(), size);
+/// Call `on_file` with the path and size of every regular file under
+/// `path` in `fs`, and `on_error` for everything that can't be read
+///
+/// Symlinks are skipped for security unless `follow_links` is set. The walk
+/// stops early once `cancel` is cancelled.
+pub(crate) fn visit_files(
+    fs: &dyn FileSystem,
+    path: &Path,
+    follow_links: bool,
+    cancel: &CancellationToken,
+    mut on_file: impl FnMut(&Path, u64),
+    mut on_error: impl FnMut(Error),
+) {
+    match fs.metadata(path) {
+        Ok(metadata) if metadata.file_type == FileType::File => on_file(path, metadata.len),
+        Ok(metadata) if metadata.file_type == FileType::Dir => {
+            let mut walk = Walk {
+                fs,
+                follow_links,
+                cancel,
+                ancestors: Vec::new(),
+                on_file: &mut on_file,
+                on_error: &mut on_error,
+            };
+            walk.dir(path);
+        }
+        Ok(_) => {}
+        // A root that was never there didn't vanish; it was mistyped
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            on_error(Error::RootNotFound(path.to_path_buf()))
+        }
+        Err(e) => on_error(Error::io(path, e)),
+    }
+}
+
+/// A depth-first walk started by [`visit_files`]
+struct Walk<'a> {
+    fs: &'a dyn FileSystem,
+    follow_links: bool,
+    cancel: &'a CancellationToken,
+    /// Resolved paths of the directories being walked, when following links
+    ancestors: Vec<PathBuf>,
+    on_file: &'a mut dyn FnMut(&Path, u64),
+    on_error: &'a mut dyn FnMut(Error),
+}
+
+impl Walk<'_> {
+    fn dir(&mut self, dir: &Path) {
+        if self.follow_links {
+            match self.fs.canonicalize(dir) {
+                Ok(resolved) if self.ancestors.contains(&resolved) => {
+                    return (self.on_error)(Error::SymlinkLoop(dir.to_path_buf()));
                 }
+                Ok(resolved) => self.ancestors.push(resolved),
+                Err(e) => return (self.on_error)(Error::io(dir, e)),
             }
         }
-    } else if path.is_dir() {
-        for entry in WalkDir::new(path).follow_links(false) {
-            match entry {
-                Ok(entry) => {
-                    // Skip symlinks for security
-                    if entry.file_type().is_symlink() {
-                        continue;
+
+        match self.fs.read_dir(dir) {
+            Ok(mut entries) => {
+                entries.sort();
+                for entry in entries {
+                    if self.cancel.is_cancelled() {
+                        break;
                     }
-                    if entry.file_type().is_file() {
-                        if let Ok(metadata) = entry.metadata() {
-                            let size = metadata.len();
-                            if size > 0 {
-                                // Skip empty files
-                                let file_info = FileInfo::new(entry.path().to_path_buf(), size);
-                                files_by_size.entry(size).or_default().push(file_info);
-                                *total_files += 1;
-
-                                if verbose {
-                                    println!(
-                                        "  Found file: {} ({} bytes)",
-                                        entry.path().display(),
-                                        size
-                                    );
-                                }
-                            }
+                    let metadata = if self.follow_links {
+                        self.fs.metadata(&entry)
+                    } else {
+                        self.fs.symlink_metadata(&entry)
+                    };
+                    match metadata {
+                        Ok(metadata) if metadata.file_type == FileType::File => {
+                            (self.on_file)(&entry, metadata.len)
                         }
+                        Ok(metadata) if metadata.file_type == FileType::Dir => self.dir(&entry),
+                        // Skip symlinks for security
+                        Ok(_) => {}
+                        Err(e) => (self.on_error)(Error::io(&entry, e)),
                     }
                 }
-                Err(e) => {
-                    eprintln!(
-                        "Warning: Could not access {}: {}",
-                        e.path().unwrap_or(Path::new("unknown")).display(),
-                        e
-                    );
-                }
             }
+            Err(e) => (self.on_error)(Error::io(dir, e)),
         }
-    }
 
-    Ok(())
+        if self.follow_links {
+            self.ancestors.pop();
+        }
+    }
 }
 
 /// Helper function to collect files for space calculation
-pub fn collect_files_for_size_calc(
-    path: &Path,
-) -> Result<Vec<FileInfo>, Box<dyn std::error::Error>> {
+///
+/// Fails if `path` itself can't be read; files below it that can't be read
+/// are left out.
+pub fn collect_files_for_size_calc(path: &Path) -> Result<Vec<FileInfo>> {
     let mut files = Vec::new();
-
-    if path.is_file() {
-        if let Ok(metadata) = path.metadata() {
-            let size = metadata.len();
+    let mut root_error = None;
+    visit_files(
+        &LocalFs,
+        path,
+        false,
+        &CancellationToken::new(),
+        |file, size| {
             if size > 0 {
-                files.push(FileInfo::new(path.to_path_buf(), size));
+                files.push(FileInfo::new(file.to_path_buf(), size));
             }
-        }
-    } else if path.is_dir() {
-        for entry in WalkDir::new(path).follow_links(false).into_iter().flatten() {
-            // Skip symlinks for security
-            if entry.file_type().is_symlink() {
-                continue;
-            }
-            if entry.file_type().is_file() {
-                if let Ok(metadata) = entry.metadata() {
-                    let size = metadata.len();
-                    if size > 0 {
-                        files.push(FileInfo::new(entry.path().to_path_buf(), size));
-                    }
-                }
+        },
+        |e| {
+            if e.path() == Some(path) {
+                root_error.get_or_insert(e);
             }
-        }
+        },
+    );
+
+    match root_error {
+        Some(e) => Err(e),
+        None => Ok(files),
     }
+}
 
-    Ok(files)
+/// Directory for file-dedup's own state, such as the journal and sessions
+pub fn data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .unwrap_or_default()
+        .join("file-dedup")
+}
+
+/// Modification time of `path` in seconds since the Unix epoch
+pub fn modification_time(path: &Path) -> Option<u64> {
+    fs::symlink_metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+// Paths written to disk as raw bytes, so names that aren't valid UTF-8
+// survive
+
+#[cfg(unix)]
+pub(crate) fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+pub(crate) fn bytes_to_path(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn bytes_to_path(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
 }
 
 /// Calculate potential space savings from removing duplicates
@@ -182,57 +369,131 @@ pub fn calculate_potential_savings(files: &[FileInfo]) -> u64 {
 pub struct DuplicateGroup {
     pub files: Vec<FileInfo>,
     pub size: u64,
-    pub hash: String,
+    pub hash: Digest,
 }
 
 impl DuplicateGroup {
-    pub fn new(files: Vec<FileInfo>, size: u64, hash: String) -> Self {
+    pub fn new(files: Vec<FileInfo>, size: u64, hash: Digest) -> Self {
         Self { files, size, hash }
     }
+
+    /// Bytes freed by keeping a single copy
+    pub fn wasted(&self) -> u64 {
+        self.size
+            .saturating_mul(self.files.len().saturating_sub(1) as u64)
+    }
+}
+
+/// Order in which duplicate groups are listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortOrder {
+    /// Most bytes freed first
+    Wasted,
+    /// Largest files first
+    Size,
+    /// Most copies first
+    Count,
+    /// Alphabetically by the first path
+    Path,
+}
+
+/// Sort `groups` by `order`
+///
+/// Ties are broken by the first path and then the hash, so the result doesn't
+/// depend on the order the groups were found in.
+pub fn sort_groups(groups: &mut [DuplicateGroup], order: SortOrder) {
+    fn first_path(g: &DuplicateGroup) -> Option<&PathBuf> {
+        g.files.first().map(|f| &f.path)
+    }
+    groups.sort_by(|a, b| {
+        let primary = match order {
+            SortOrder::Wasted => b.wasted().cmp(&a.wasted()),
+            SortOrder::Size => b.size.cmp(&a.size),
+            SortOrder::Count => b.files.len().cmp(&a.files.len()),
+            SortOrder::Path => std::cmp::Ordering::Equal,
+        };
+        primary
+            .then_with(|| first_path(a).cmp(&first_path(b)))
+            .then_with(|| a.hash.cmp(&b.hash))
+    });
 }
 
 /// Find duplicate groups and return them for processing
+///
+/// Files in a group are sorted by path and groups by wasted space.
 pub fn find_duplicate_groups(
     files_by_size: HashMap<u64, Vec<FileInfo>>,
-    verbose: bool,
-) -> anyhow::Result<Vec<DuplicateGroup>> {
-    let mut duplicate_groups = Vec::new();
-
-    for (size, mut files) in files_by_size {
-        if files.len() < 2 {
-            continue; // No duplicates possible
-        }
-
-        if verbose {
-            println!("Checking {} files of size {} bytes", files.len(), size);
-        }
+    warnings: &mut Vec<Error>,
+) -> Result<Vec<DuplicateGroup>> {
+    find_duplicate_groups_with(files_by_size, HashAlgorithm::default(), warnings)
+}
 
-        // Calculate hashes for files with the same size
-        let mut files_by_hash: HashMap<String, Vec<FileInfo>> = HashMap::new();
+/// Like [`find_duplicate_groups`], hashing with `algorithm`
+///
+/// Files that can't be hashed are added to `warnings`.
+pub fn find_duplicate_groups_with(
+    files_by_size: HashMap<u64, Vec<FileInfo>>,
+    algorithm: HashAlgorithm,
+    warnings: &mut Vec<Error>,
+) -> Result<Vec<DuplicateGroup>> {
+    Ok(find_groups_into(
+        files_by_size,
+        algorithm,
+        &CancellationToken::new(),
+        &mut NoProgress,
+        warnings,
+    ))
+}
 
-        for file in &mut files {
-            match file.calculate_hash() {
-                Ok(hash) => {
-                    files_by_hash
-                        .entry(hash.to_string())
-                        .or_default()
-                        .push(file.clone());
-                }
-                Err(e) => {
-                    eprintln!("Warning: Could not hash {}: {}", file.path.display(), e);
-                }
-            }
-        }
+/// Like [`find_duplicate_groups_with`], reporting hashing progress to
+/// `progress`
+///
+/// Files that can't be hashed are left out silently.
+pub fn find_duplicate_groups_with_progress(
+    files_by_size: HashMap<u64, Vec<FileInfo>>,
+    algorithm: HashAlgorithm,
+    progress: &mut dyn ProgressObserver,
+) -> Result<Vec<DuplicateGroup>> {
+    Ok(find_groups_into(
+        files_by_size,
+        algorithm,
+        &CancellationToken::new(),
+        progress,
+        &mut Vec::new(),
+    ))
+}
 
-        // Create duplicate groups for files with same hash
-        for (hash, duplicate_files) in files_by_hash {
-            if duplicate_files.len() > 1 {
-                duplicate_groups.push(DuplicateGroup::new(duplicate_files, size, hash));
-            }
-        }
-    }
+/// Like [`find_duplicate_groups_with_progress`], adding the files that can't
+/// be hashed to `warnings` and stopping early once `cancel` is cancelled
+pub(crate) fn find_groups_into(
+    files_by_size: HashMap<u64, Vec<FileInfo>>,
+    algorithm: HashAlgorithm,
+    cancel: &CancellationToken,
+    progress: &mut dyn ProgressObserver,
+    warnings: &mut Vec<Error>,
+) -> Vec<DuplicateGroup> {
+    let (files, bytes) = files_by_size
+        .iter()
+        .filter(|(_, files)| files.len() > 1)
+        .fold((0, 0), |(count, bytes), (size, files)| {
+            (
+                count + files.len() as u64,
+                bytes + size * files.len() as u64,
+            )
+        });
+    progress.on_progress(ProgressEvent::HashingStarted { files, bytes });
 
-    Ok(duplicate_groups)
+    let mut duplicate_groups = Vec::new();
+    Scanner::new()
+        .algorithm(algorithm)
+        .cancel_token(cancel.clone())
+        .hash_batch(files_by_size, progress, warnings, &mut |group| {
+            duplicate_groups.push(group)
+        });
+    progress.on_progress(ProgressEvent::HashingFinished);
+
+    sort_groups(&mut duplicate_groups, SortOrder::Wasted);
+    duplicate_groups
 }
 
 #[cfg(test)]
@@ -255,13 +516,30 @@ mod tests {
         let file_path = create_test_file(temp_dir.path(), "test.txt", b"Hello, World!");
 
         let mut file_info = FileInfo::new(file_path, 13);
-        let hash1 = file_info.calculate_hash().unwrap().to_string();
-        let hash2 = file_info.calculate_hash().unwrap().to_string();
+        let hash1 = file_info.calculate_hash().unwrap();
+        let hash2 = file_info.calculate_hash().unwrap();
 
         // Hash should be calculated once and cached
         assert_eq!(hash1, hash2);
-        assert!(!hash1.is_empty());
-        assert_eq!(hash1.len(), 16); // xxHash (XXH3) hex length (64-bit)
+        assert_eq!(file_info.hash, Some(hash1));
+        assert_eq!(hash1.as_bytes().len(), 8); // xxHash (XXH3), 64-bit
+        assert_eq!(hash1.to_hex().len(), 16);
+    }
+
+    /// The per-file memory cost of a scan; rerun with `cargo test layout`
+    ///
+    /// With the hash stored as a hex `String`, a hashed file took 56 bytes
+    /// plus a 16 to 64 byte heap allocation for the hex text. An inline
+    /// [`Digest`] needs no allocation at all.
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_file_info_layout() {
+        use std::mem::size_of;
+
+        assert_eq!(size_of::<Digest>(), 33);
+        // The algorithm tag leaves room for `None`
+        assert_eq!(size_of::<Option<Digest>>(), size_of::<Digest>());
+        assert_eq!(size_of::<FileInfo>(), 72);
     }
 
     #[test]
@@ -345,7 +623,13 @@ mod tests {
         let mut total_files = 0;
 
         // Test collecting files from the test directory
-        collect_files(temp_dir.path(), &mut files_by_size, &mut total_files, false).unwrap();
+        collect_files(
+            temp_dir.path(),
+            &mut files_by_size,
+            &mut total_files,
+            &mut Vec::new(),
+        )
+        .unwrap();
 
         // Should find all 7 files
         assert_eq!(total_files, 7);
@@ -382,10 +666,16 @@ mod tests {
         let mut total_files = 0;
 
         // Collect files first
-        collect_files(temp_dir.path(), &mut files_by_size, &mut total_files, false).unwrap();
+        collect_files(
+            temp_dir.path(),
+            &mut files_by_size,
+            &mut total_files,
+            &mut Vec::new(),
+        )
+        .unwrap();
 
         // Find duplicate groups
-        let duplicate_groups = find_duplicate_groups(files_by_size, false).unwrap();
+        let duplicate_groups = find_duplicate_groups(files_by_size, &mut Vec::new()).unwrap();
 
         // Should find 1 duplicate group (the 3 files with identical content)
         assert_eq!(duplicate_groups.len(), 1);
@@ -393,6 +683,77 @@ mod tests {
         let group = &duplicate_groups[0];
         assert_eq!(group.files.len(), 3);
         assert_eq!(group.size, 25); // Size of "This is duplicate content"
-        assert!(!group.hash.is_empty());
+        assert!(!group.hash.as_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_progress_events_add_up() {
+        let temp_dir = create_test_directory_structure();
+        let mut events = Vec::new();
+        let mut record = |event: ProgressEvent| events.push(event);
+
+        let mut files_by_size = HashMap::new();
+        let mut total_files = 0;
+        collect_files_with_progress(
+            temp_dir.path(),
+            &mut files_by_size,
+            &mut total_files,
+            &mut record,
+        )
+        .unwrap();
+        find_duplicate_groups_with_progress(files_by_size, HashAlgorithm::default(), &mut record)
+            .unwrap();
+
+        let found = events
+            .iter()
+            .filter(|e| matches!(e, ProgressEvent::FileFound { .. }))
+            .count();
+        let hashed: u64 = events
+            .iter()
+            .map(|e| match e {
+                ProgressEvent::BytesHashed { bytes } => *bytes,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(found, 7);
+        // Every file shares its size with another one
+        assert!(events.contains(&ProgressEvent::HashingStarted {
+            files: 7,
+            bytes: 3 * 25 + 2 * 16 + 2 * 4
+        }));
+        assert_eq!(hashed, 115);
+        assert_eq!(events.last(), Some(&ProgressEvent::HashingFinished));
+    }
+
+    #[test]
+    fn test_sort_groups_is_deterministic() {
+        let group = |paths: &[&str], size: u64, label: u8| {
+            let files = paths
+                .iter()
+                .map(|p| FileInfo::new(PathBuf::from(p), size))
+                .collect();
+            DuplicateGroup::new(files, size, Digest::new(HashAlgorithm::Xxh3, &[label; 8]))
+        };
+        let mut groups = vec![
+            group(&["/b/1", "/b/2"], 300, b'b'),
+            group(&["/c/1", "/c/2", "/c/3", "/c/4"], 100, b'c'),
+            group(&["/a/1", "/a/2", "/a/3"], 150, b'a'),
+        ];
+        let hashes = |groups: &[DuplicateGroup]| -> String {
+            groups
+                .iter()
+                .map(|g| g.hash.as_bytes()[0] as char)
+                .collect()
+        };
+
+        // Wasted: b = 300, c = 300, a = 300, so ties fall back to the path
+        sort_groups(&mut groups, SortOrder::Wasted);
+        assert_eq!(hashes(&groups), "abc");
+        sort_groups(&mut groups, SortOrder::Size);
+        assert_eq!(hashes(&groups), "bac");
+        sort_groups(&mut groups, SortOrder::Count);
+        assert_eq!(hashes(&groups), "cab");
+        sort_groups(&mut groups, SortOrder::Path);
+        assert_eq!(hashes(&groups), "abc");
     }
 }
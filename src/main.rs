@@ -1,206 +1,1433 @@
-use clap::Parser;
+mod progress_bars;
+mod tui;
+
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use colored::*;
-use dialoguer::{Confirm, Select};
-use file_dedup::{
-    calculate_potential_savings, collect_files, collect_files_for_size_calc, find_duplicate_groups,
-    DuplicateGroup, FileInfo,
-};
-use std::collections::HashMap;
+use dialoguer::{Confirm, Input, MultiSelect, Select};
+use file_dedup::actions::{apply_action, Action, ActionKind};
+use file_dedup::cancel::CancellationToken;
+use file_dedup::compare::compare;
+use file_dedup::dirs::{find_duplicate_dirs, sort_dir_groups};
+use file_dedup::error::Error;
+use file_dedup::hash::{Digest, HashAlgorithm};
+use file_dedup::index::{Index, IndexStats};
+use file_dedup::journal::{pending_operations, undo_entry, Journal, UndoOutcome};
+use file_dedup::overlap::{find_overlaps, sort_overlaps, OverlapOrder};
+use file_dedup::plan::{apply_plan, Plan, PlannedAction};
+use file_dedup::preview::{file_details, preview, FileDetails, Preview};
+use file_dedup::rules::{first_match, Rule, RuleDecision};
+use file_dedup::scanner::{ScanResult, Scanner, SymlinkPolicy};
+use file_dedup::script::write_script;
+use file_dedup::session::{GroupStatus, Revalidated, Session};
+use file_dedup::watch::{watch, Change, ContentSet};
+use file_dedup::{modification_time, sort_groups, DuplicateGroup, FileInfo, SortOrder};
+use progress_bars::ScanProgress;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Lines shown when previewing a duplicate group
+const PREVIEW_LINES: usize = 12;
 
 /// A minimal file deduplication tool that finds duplicate files using xxHash
 #[derive(Parser)]
-#[command(author, version, about = "A minimal file deduplication tool with report and interactive modes", long_about = None)]
+#[command(
+    author,
+    version,
+    about = "A minimal file deduplication tool with report and interactive modes",
+    long_about = None,
+    subcommand_negates_reqs = true,
+    after_help = "Exit status: 0 nothing found, 1 duplicates found, 2 fatal error, \
+                  3 files skipped with --strict"
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Paths to scan for duplicates (files or directories)
-    #[arg(required = true)]
+    #[arg(required_unless_present = "resume")]
     paths: Vec<PathBuf>,
 
     /// Enable verbose output
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     verbose: bool,
 
     /// Enable interactive mode for duplicate resolution
     #[arg(short, long)]
     interactive: bool,
 
+    /// File that interactive progress is saved to after every group
+    #[arg(long, value_name = "FILE")]
+    session: Option<PathBuf>,
+
+    /// Continue an interactive session from its next unresolved group
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["paths", "session", "tui"])]
+    resume: Option<PathBuf>,
+
+    /// Browse duplicate groups in a full-screen terminal UI
+    #[arg(long, conflicts_with = "interactive")]
+    tui: bool,
+
+    #[command(flatten)]
+    action: ActionArgs,
+
+    #[command(flatten)]
+    ranking: RankingArgs,
+
+    #[command(flatten)]
+    scanning: ScanArgs,
+
+    /// Hash algorithm used to compare file contents [default: xxh3]
+    #[arg(long, value_enum, global = true, value_name = "ALGORITHM")]
+    hash: Option<HashAlgorithm>,
+
+    /// Exit with status 3 if any file or directory had to be skipped
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Journal file recording every destructive operation
+    #[arg(long, global = true, value_name = "FILE")]
+    journal: Option<PathBuf>,
+
     /// Disable colored output
-    #[arg(long)]
+    #[arg(long, global = true)]
     no_color: bool,
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+#[derive(Subcommand)]
+enum Command {
+    /// Reverse operations recorded in the journal, most recent first
+    Undo {
+        /// Only reverse the N most recent operations
+        #[arg(long, value_name = "N")]
+        last: Option<usize>,
+
+        /// Show what would be reversed without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Write the planned action for every duplicate to a reviewable file
+    Plan {
+        /// Paths to scan for duplicates (files or directories)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Where to write the plan
+        #[arg(short, long, value_name = "FILE", default_value = "dedup-plan.json")]
+        output: PathBuf,
+
+        #[command(flatten)]
+        action: ActionArgs,
+
+        #[command(flatten)]
+        ranking: RankingArgs,
+
+        #[command(flatten)]
+        scanning: ScanArgs,
+    },
+
+    /// Execute a (possibly edited) plan, skipping files changed since planning
+    Apply {
+        /// Plan file written by `plan`
+        plan: PathBuf,
+    },
+
+    /// Write a POSIX shell script that removes duplicates when run
+    Script {
+        /// Paths to scan for duplicates (files or directories)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Where to write the script
+        #[arg(short, long, value_name = "FILE", default_value = "dedup.sh")]
+        output: PathBuf,
+
+        #[command(flatten)]
+        action: ActionArgs,
+
+        #[command(flatten)]
+        ranking: RankingArgs,
+
+        #[command(flatten)]
+        scanning: ScanArgs,
+    },
+
+    /// Report directories whose whole trees are identical
+    Dirs {
+        /// Paths to scan for duplicate directories
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        #[command(flatten)]
+        ranking: RankingArgs,
+
+        #[command(flatten)]
+        scanning: ScanArgs,
+    },
+
+    /// Report which files under the source already exist under the target
+    Compare {
+        /// Path whose files are looked up (repeatable)
+        #[arg(long, required = true, value_name = "PATH")]
+        source: Vec<PathBuf>,
+
+        /// Path searched for copies of the source files (repeatable)
+        #[arg(long, required = true, value_name = "PATH")]
+        target: Vec<PathBuf>,
+    },
+
+    /// List files under the source with no copy under any backup
+    Unique {
+        /// Path whose files must be backed up (repeatable)
+        #[arg(long, required = true, value_name = "PATH")]
+        source: Vec<PathBuf>,
+
+        /// Backup path searched for copies (repeatable)
+        #[arg(long, required = true, value_name = "PATH")]
+        backup: Vec<PathBuf>,
+
+        #[command(flatten)]
+        ranking: RankingArgs,
+    },
+
+    /// Watch directories and report new duplicates as they appear
+    Watch {
+        /// Directories to watch
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Print every duplicate as one JSON object per line
+        #[arg(long)]
+        ndjson: bool,
+
+        /// Resolve new duplicates right away with --action
+        #[arg(long)]
+        apply: bool,
+
+        #[command(flatten)]
+        action: ActionArgs,
+
+        /// How long a file must be left alone before it is checked
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        settle: u64,
+    },
+
+    /// Maintain and query a persistent index of file contents
+    Index {
+        #[command(subcommand)]
+        command: IndexCommand,
+
+        /// Index database file
+        #[arg(long, value_name = "FILE", global = true)]
+        db: Option<PathBuf>,
+    },
+
+    /// Report which pairs of directories share the most duplicate content
+    Overlap {
+        /// Paths to scan for duplicates (files or directories)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Rank pairs by shared bytes or by overlap relative to their size
+        #[arg(long, value_enum, default_value_t = OverlapOrder::Bytes)]
+        by: OverlapOrder,
+
+        #[command(flatten)]
+        ranking: RankingArgs,
+
+        #[command(flatten)]
+        scanning: ScanArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum IndexCommand {
+    /// Add a root to the index and hash every file under it
+    Build {
+        /// Directory to index
+        root: PathBuf,
+    },
+
+    /// Refresh indexed roots, rehashing only files that changed
+    Update {
+        /// Roots to refresh (default: every indexed root)
+        roots: Vec<PathBuf>,
+    },
+
+    /// Report indexed files with the same content as the given files
+    Query {
+        /// Files to look up
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+/// How duplicates are resolved: every file except the kept one gets this action
+#[derive(clap::Args)]
+struct ActionArgs {
+    /// What to do with every duplicate except the kept copy
+    #[arg(long, value_enum, default_value_t = ActionKind::Delete)]
+    action: ActionKind,
+
+    /// Directory that duplicates are moved into with `--action move`
+    #[arg(long, value_name = "DIR", required_if_eq("action", "move"))]
+    move_to: Option<PathBuf>,
+}
+
+impl ActionArgs {
+    fn to_action(&self) -> anyhow::Result<Action> {
+        Action::new(self.action, self.move_to.clone())
+    }
+}
+
+/// How files are scanned and compared
+#[derive(clap::Args)]
+struct ScanArgs {
+    /// Hold at most about this many MiB of file information while scanning,
+    /// spilling the rest to temporary files
+    #[arg(long, value_name = "MIB")]
+    max_memory: Option<u64>,
+
+    /// Number of threads hashing files
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    threads: usize,
+
+    /// Skip files smaller than this many bytes
+    #[arg(long, value_name = "BYTES")]
+    min_size: Option<u64>,
+
+    /// Skip files larger than this many bytes
+    #[arg(long, value_name = "BYTES")]
+    max_size: Option<u64>,
+
+    /// Scan the targets of symbolic links instead of skipping them
+    #[arg(long)]
+    follow_symlinks: bool,
+}
+
+impl ScanArgs {
+    /// A scanner for `paths` configured by these options
+    fn scanner(&self, paths: &[PathBuf], algorithm: HashAlgorithm) -> Scanner {
+        let mut scanner = Scanner::new()
+            .roots(paths)
+            .algorithm(algorithm)
+            .threads(self.threads)
+            .cancel_token(cancel_token().clone());
+        if let Some(max_memory) = self.max_memory {
+            scanner = scanner.memory_limit(max_memory.saturating_mul(1024 * 1024));
+        }
+        if let Some(min_size) = self.min_size {
+            scanner = scanner.min_size(min_size);
+        }
+        if let Some(max_size) = self.max_size {
+            scanner = scanner.max_size(max_size);
+        }
+        if self.follow_symlinks {
+            scanner = scanner.symlinks(SymlinkPolicy::Follow);
+        }
+        scanner
+    }
+}
+
+/// Which duplicate groups are listed, and in what order
+#[derive(clap::Args)]
+struct RankingArgs {
+    /// Order in which duplicate groups are listed
+    #[arg(long, value_enum, default_value_t = SortOrder::Wasted)]
+    sort: SortOrder,
+
+    /// Only list the first N groups
+    #[arg(long, value_name = "N")]
+    top: Option<usize>,
+}
+
+/// Exit statuses, the same for every command and mode
+///
+/// When several apply, `Partial` wins over `Found`, and `Found` over `Clean`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    /// Nothing was found
+    Clean = 0,
+    /// Duplicates were found (for `unique`: files without a backup)
+    Found = 1,
+    /// The command failed or its arguments were invalid
+    Fatal = 2,
+    /// With --strict: files or directories had to be skipped
+    Partial = 3,
+    /// Stopped with Ctrl-C, the shell's status for SIGINT
+    Cancelled = 130,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
+
+/// What a command found, for the exit status
+#[derive(Default)]
+struct Outcome {
+    /// Whether the command found what it looks for
+    found: bool,
+    /// Files and directories the scan had to skip, plus files an action or
+    /// undo had to leave alone
+    skipped: usize,
+}
+
+impl Outcome {
+    fn exit(&self, strict: bool) -> Exit {
+        if strict && self.skipped > 0 {
+            Exit::Partial
+        } else if self.found {
+            Exit::Found
+        } else {
+            Exit::Clean
+        }
+    }
+}
+
+impl From<&ScanResult> for Outcome {
+    fn from(result: &ScanResult) -> Self {
+        Self {
+            found: !result.groups.is_empty(),
+            skipped: result.warnings.len(),
+        }
+    }
+}
+
+/// Cancelled by the first Ctrl-C
+fn cancel_token() -> &'static CancellationToken {
+    static CANCEL: OnceLock<CancellationToken> = OnceLock::new();
+    CANCEL.get_or_init(CancellationToken::new)
+}
+
+/// Stop after the current file on the first Ctrl-C, and right away on the
+/// second
+fn install_interrupt_handler() {
+    let result = ctrlc::set_handler(|| {
+        let cancel = cancel_token();
+        if cancel.is_cancelled() {
+            std::process::exit(Exit::Cancelled as i32);
+        }
+        cancel.cancel();
+        eprintln!("\nStopping after the current file; press Ctrl-C again to quit now");
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: Ctrl-C will stop immediately: {}", e);
+    }
+}
+
+fn main() -> ExitCode {
+    // Invalid arguments exit with 2, the same as `Exit::Fatal`
+    let args = parse_args();
+    install_interrupt_handler();
 
     // Disable colored output if requested
     if args.no_color {
         colored::control::set_override(false);
     }
 
-    if args.verbose {
-        print_header("Starting file deduplication scan...");
-        print_info(&format!("Scanning paths: {:?}", args.paths));
+    let strict = args.strict;
+    match run(args) {
+        Ok(outcome) => {
+            if strict && outcome.skipped > 0 {
+                eprintln!(
+                    "Error: {} files or directories were skipped (--strict)",
+                    outcome.skipped
+                );
+            }
+            outcome.exit(strict).into()
+        }
+        Err(e) if cancel_token().is_cancelled() || is_cancelled(&e) => {
+            eprintln!("Cancelled");
+            Exit::Cancelled.into()
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            Exit::Fatal.into()
+        }
     }
+}
 
-    // Collect all files and group by size
-    let mut files_by_size: HashMap<u64, Vec<FileInfo>> = HashMap::new();
-    let mut total_files = 0;
-
-    for path in &args.paths {
-        if args.verbose {
-            print_info(&format!("Scanning: {}", path.display()));
+/// Parse the command line, rejecting options of the default mode that are
+/// given before a subcommand, which would ignore them
+fn parse_args() -> Args {
+    let mut command = Args::command();
+    let matches = command.get_matches_mut();
+    if let Some((name, _)) = matches.subcommand() {
+        let ignored = command.get_arguments().find(|arg| {
+            !arg.is_global_set()
+                && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+        });
+        if let Some(arg) = ignored {
+            let arg = match arg.get_long() {
+                Some(long) => format!("--{}", long),
+                None => arg.get_id().to_string(),
+            };
+            command
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("'{}' cannot be used with the '{}' subcommand", arg, name),
+                )
+                .exit();
         }
+    }
+    Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+fn is_cancelled(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<Error>(), Some(Error::Cancelled))
+}
+
+fn run(args: Args) -> anyhow::Result<Outcome> {
+    let journal_path = args.journal.clone().unwrap_or_else(Journal::default_path);
+    let algorithm = args.hash.unwrap_or_default();
 
-        collect_files(path, &mut files_by_size, &mut total_files, args.verbose)?;
+    match args.command {
+        Some(Command::Undo { last, dry_run }) => {
+            let skipped = handle_undo(&journal_path, last, dry_run)?;
+            return Ok(Outcome {
+                found: false,
+                skipped,
+            });
+        }
+        Some(Command::Plan {
+            paths,
+            output,
+            action,
+            ranking,
+            scanning,
+        }) => {
+            let action = action.to_action()?;
+            let result = scan(&paths, &ranking, &scanning, algorithm, args.verbose)?;
+            handle_plan(&result.groups, &action, &output)?;
+            return Ok(Outcome::from(&result));
+        }
+        Some(Command::Script {
+            paths,
+            output,
+            action,
+            ranking,
+            scanning,
+        }) => {
+            let action = action.to_action()?;
+            let result = scan(&paths, &ranking, &scanning, algorithm, args.verbose)?;
+            handle_script(&result.groups, &action, &output)?;
+            return Ok(Outcome::from(&result));
+        }
+        Some(Command::Dirs {
+            paths,
+            ranking,
+            scanning,
+        }) => {
+            return handle_dirs(&paths, &ranking, &scanning, algorithm, args.verbose);
+        }
+        Some(Command::Overlap {
+            paths,
+            by,
+            ranking,
+            scanning,
+        }) => {
+            return handle_overlap(&paths, by, &ranking, &scanning, algorithm, args.verbose);
+        }
+        Some(Command::Compare { source, target }) => {
+            return handle_compare(&source, &target, algorithm, args.verbose);
+        }
+        Some(Command::Unique {
+            source,
+            backup,
+            ranking,
+        }) => {
+            return handle_unique(&source, &backup, &ranking, algorithm, args.verbose);
+        }
+        Some(Command::Watch {
+            paths,
+            ndjson,
+            apply,
+            action,
+            settle,
+        }) => {
+            let action = apply.then(|| action.to_action()).transpose()?;
+            let mut journal = Journal::open(&journal_path)?;
+            let settle = Duration::from_millis(settle);
+            let skipped = handle_watch(
+                &paths,
+                ndjson,
+                action.as_ref(),
+                settle,
+                algorithm,
+                &mut journal,
+            )?;
+            return Ok(Outcome {
+                found: false,
+                skipped,
+            });
+        }
+        Some(Command::Index { command, db }) => {
+            let db = db.unwrap_or_else(Index::default_path);
+            return handle_index(command, &db, args.hash);
+        }
+        Some(Command::Apply { plan }) => {
+            let mut journal = Journal::open(&journal_path)?;
+            let skipped = handle_apply(&plan, &mut journal)?;
+            return Ok(Outcome {
+                found: false,
+                skipped,
+            });
+        }
+        None => {}
     }
 
-    if args.verbose {
-        print_success(&format!("Found {} files total", total_files));
-        print_header("Checking for duplicates...");
+    if let Some(session_path) = &args.resume {
+        let mut session = Session::load(session_path)?;
+        let action = args.action.to_action()?;
+        session.check_action(&action)?;
+        let mut journal = Journal::open(&journal_path)?;
+        let skipped = handle_interactive_mode(&mut session, session_path, &action, &mut journal)?;
+        // The duplicates were found by the scan that started the session
+        return Ok(Outcome {
+            found: !session.groups.is_empty(),
+            skipped,
+        });
     }
 
     // Find duplicate groups
-    let duplicate_groups = find_duplicate_groups(files_by_size, args.verbose)?;
+    let result = scan(
+        &args.paths,
+        &args.ranking,
+        &args.scanning,
+        algorithm,
+        args.verbose,
+    )?;
+    let mut outcome = Outcome::from(&result);
+    let duplicate_groups = result.groups;
 
     if duplicate_groups.is_empty() {
         println!("No duplicate files found!");
-        return Ok(());
+        return Ok(outcome);
     }
 
     if args.interactive {
-        handle_interactive_mode(duplicate_groups)?;
+        let action = args.action.to_action()?;
+        let mut journal = Journal::open(&journal_path)?;
+        let session_path = args.session.clone().unwrap_or_else(Session::default_path);
+        let mut session = Session::new(&args.paths, &duplicate_groups, &action);
+        session.save(&session_path)?;
+        outcome.skipped +=
+            handle_interactive_mode(&mut session, &session_path, &action, &mut journal)?;
+    } else if args.tui {
+        let action = args.action.to_action()?;
+        let mut journal = Journal::open(&journal_path)?;
+        outcome.skipped += handle_tui_mode(duplicate_groups, &action, &mut journal)?;
+    } else {
+        handle_report_mode(duplicate_groups)?;
+    }
+
+    Ok(outcome)
+}
+
+/// Scan `paths` and rank the duplicate groups as requested
+fn scan(
+    paths: &[PathBuf],
+    ranking: &RankingArgs,
+    scanning: &ScanArgs,
+    algorithm: HashAlgorithm,
+    verbose: bool,
+) -> anyhow::Result<ScanResult> {
+    let mut result = scan_all(paths, scanning, algorithm, verbose)?;
+    let duplicate_groups = &mut result.groups;
+    sort_groups(duplicate_groups, ranking.sort);
+
+    if let Some(top) = ranking.top.filter(|&top| top < duplicate_groups.len()) {
+        print_info(&format!(
+            "Showing the top {} of {} duplicate groups",
+            top,
+            duplicate_groups.len()
+        ));
+        duplicate_groups.truncate(top);
+    }
+
+    Ok(result)
+}
+
+/// Collect files under `paths` and group the ones with identical content
+fn scan_all(
+    paths: &[PathBuf],
+    scanning: &ScanArgs,
+    algorithm: HashAlgorithm,
+    verbose: bool,
+) -> anyhow::Result<ScanResult> {
+    if verbose {
+        print_header("Starting file deduplication scan...");
+        for path in paths {
+            print_info(&format!("Scanning: {}", path.display()));
+        }
+        if let Some(max_memory) = scanning.max_memory {
+            print_info(&format!(
+                "Holding at most about {} MiB of file information",
+                max_memory
+            ));
+        }
+    }
+
+    let mut progress = ScanProgress::new(verbose);
+    let result = scanning
+        .scanner(paths, algorithm)
+        .scan_with_progress(&mut progress);
+    let result = match result {
+        Err(Error::Cancelled) => {
+            let summary = progress.summary();
+            drop(progress);
+            print_warning(&format!("Scan stopped: {}", summary));
+            return Err(Error::Cancelled.into());
+        }
+        result => result?,
+    };
+    report_skipped(&result.warnings);
+
+    if verbose {
+        let stats = &result.stats;
+        print_success(&format!("Found {} files total", stats.files_found));
+        print_info(&format!(
+            "Hashed {} files sharing a size ({} hashes, {} batch(es), {} thread(s))",
+            stats.files_hashed,
+            algorithm,
+            stats.batches,
+            scanning.threads.max(1)
+        ));
+        print_info(&format!("Scan took {:.2}s", stats.elapsed.as_secs_f64()));
+    }
+
+    Ok(result)
+}
+
+/// Tell the user about every file or directory a scan skipped
+fn report_skipped(warnings: &[Error]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+fn handle_report_mode(duplicate_groups: Vec<DuplicateGroup>) -> anyhow::Result<()> {
+    print_header("Found duplicate files:\n");
+
+    let mut total_duplicate_files = 0;
+
+    for (group_idx, group) in duplicate_groups.iter().enumerate() {
+        total_duplicate_files += group.files.len();
+
+        print_duplicate_group_header(group_idx, duplicate_groups.len(), group.size, &group.hash);
+        println!();
+
+        for (i, file) in group.files.iter().enumerate() {
+            let marker = if i == 0 {
+                "KEEP".green().bold()
+            } else {
+                "DUP".red().bold()
+            };
+            let parent_dir = file
+                .path
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "".to_string());
+            let filename = file
+                .path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_else(|| file.path.display().to_string());
+
+            println!("    {} Status: {}", "📄".blue(), marker);
+            println!("    {} Location: {}", "📍".yellow(), parent_dir.dimmed());
+            println!("    {} Title: {}", "🏷️".cyan(), filename.bold());
+            println!();
+        }
+        println!();
+    }
+
+    // Summary
+    println!();
+    print_header("Summary:");
+    print_info(&format!(
+        "Found {} duplicate groups",
+        duplicate_groups.len()
+    ));
+    print_info(&format!("Total duplicate files: {}", total_duplicate_files));
+    print_warning(&format!(
+        "Files that could be removed: {}",
+        total_duplicate_files - duplicate_groups.len()
+    ));
+
+    // What the reported groups waste, counting each hash group once
+    let potential_savings: u64 = duplicate_groups.iter().map(|g| g.wasted()).sum();
+
+    if potential_savings > 0 {
+        print_success(&format!(
+            "Potential space savings: {}",
+            format_file_size(potential_savings)
+        ));
+    }
+
+    Ok(())
+}
+
+fn handle_dirs(
+    paths: &[PathBuf],
+    ranking: &RankingArgs,
+    scanning: &ScanArgs,
+    algorithm: HashAlgorithm,
+    verbose: bool,
+) -> anyhow::Result<Outcome> {
+    let result = scan_all(paths, scanning, algorithm, verbose)?;
+    let duplicate_groups = &result.groups;
+    let mut dir_groups = find_duplicate_dirs(paths, duplicate_groups);
+    let outcome = Outcome {
+        found: !dir_groups.is_empty(),
+        skipped: result.warnings.len(),
+    };
+
+    if dir_groups.is_empty() {
+        println!("No duplicate directories found!");
+        return Ok(outcome);
+    }
+
+    sort_dir_groups(&mut dir_groups, ranking.sort);
+    if let Some(top) = ranking.top.filter(|&top| top < dir_groups.len()) {
+        print_info(&format!(
+            "Showing the top {} of {} duplicate directory groups",
+            top,
+            dir_groups.len()
+        ));
+        dir_groups.truncate(top);
+    }
+
+    print_header("Found duplicate directories:\n");
+
+    for (group_idx, group) in dir_groups.iter().enumerate() {
+        println!(
+            "{} {} {} {} {}",
+            "📂".bold(),
+            "Duplicate Directory Group".bold().magenta(),
+            format!("{}/{}", group_idx + 1, dir_groups.len())
+                .bold()
+                .white(),
+            format!("({}, {} files)", format_file_size(group.size), group.files).dimmed(),
+            format!("Fingerprint: {}", &group.fingerprint.to_hex()[..8]).dimmed()
+        );
+        println!();
+
+        for (i, dir) in group.dirs.iter().enumerate() {
+            let marker = if i == 0 {
+                "KEEP".green().bold()
+            } else {
+                "DUP".red().bold()
+            };
+            println!("    {} {} {}", "📍".yellow(), marker, dir.display());
+        }
+        println!();
+    }
+
+    // Summary
+    println!();
+    print_header("Summary:");
+    print_info(&format!(
+        "Found {} duplicate directory groups",
+        dir_groups.len()
+    ));
+    let wasted: u64 = dir_groups.iter().map(|g| g.wasted()).sum();
+    print_success(&format!(
+        "Potential space savings: {}",
+        format_file_size(wasted)
+    ));
+
+    Ok(outcome)
+}
+
+fn handle_compare(
+    sources: &[PathBuf],
+    targets: &[PathBuf],
+    algorithm: HashAlgorithm,
+    verbose: bool,
+) -> anyhow::Result<Outcome> {
+    if verbose {
+        print_header("Comparing source against target...");
+        print_info(&format!("Source: {:?}", sources));
+        print_info(&format!("Target: {:?}", targets));
+    }
+
+    let mut progress = ScanProgress::new(verbose);
+    let comparison = compare(sources, targets, algorithm, cancel_token(), &mut progress);
+    let comparison = match comparison {
+        Err(Error::Cancelled) => {
+            let summary = progress.summary();
+            drop(progress);
+            print_warning(&format!("Comparison stopped: {}", summary));
+            return Err(Error::Cancelled.into());
+        }
+        comparison => comparison?,
+    };
+    report_skipped(&comparison.warnings);
+    let outcome = Outcome {
+        found: !comparison.matches.is_empty(),
+        skipped: comparison.warnings.len(),
+    };
+
+    if comparison.matches.is_empty() {
+        println!("None of the source files exist in the target!");
+        return Ok(outcome);
+    }
+
+    print_header("Source files already in the target:\n");
+
+    for found in &comparison.matches {
+        println!(
+            "    {} {} {}",
+            "📄".blue(),
+            found.source.display().to_string().bold(),
+            format!("({})", format_file_size(found.size)).dimmed()
+        );
+        for target in &found.targets {
+            println!(
+                "      {} {}",
+                "↳".green(),
+                target.display().to_string().dimmed()
+            );
+        }
+        println!();
+    }
+
+    // Summary
+    println!();
+    print_header("Summary:");
+    print_info(&format!(
+        "{} of {} source files already exist in the target",
+        comparison.matches.len(),
+        comparison.source_files
+    ));
+    let matched_bytes: u64 = comparison.matches.iter().map(|m| m.size).sum();
+    print_success(&format!(
+        "Already backed up: {}",
+        format_file_size(matched_bytes)
+    ));
+
+    Ok(outcome)
+}
+
+fn handle_unique(
+    sources: &[PathBuf],
+    backups: &[PathBuf],
+    ranking: &RankingArgs,
+    algorithm: HashAlgorithm,
+    verbose: bool,
+) -> anyhow::Result<Outcome> {
+    if verbose {
+        print_header("Checking source against backups...");
+        print_info(&format!("Source: {:?}", sources));
+        print_info(&format!("Backups: {:?}", backups));
+    }
+
+    let mut progress = ScanProgress::new(verbose);
+    let comparison = compare(sources, backups, algorithm, cancel_token(), &mut progress);
+    let comparison = match comparison {
+        Err(Error::Cancelled) => {
+            let summary = progress.summary();
+            drop(progress);
+            print_warning(&format!("Comparison stopped: {}", summary));
+            return Err(Error::Cancelled.into());
+        }
+        comparison => comparison?,
+    };
+    report_skipped(&comparison.warnings);
+    let outcome = Outcome {
+        found: !comparison.missing.is_empty(),
+        skipped: comparison.warnings.len(),
+    };
+    let mut missing = comparison.missing;
+
+    if missing.is_empty() {
+        print_success("Every source file has a copy in the backups!");
+        return Ok(outcome);
+    }
+
+    // Biggest risks first unless sorted by path
+    match ranking.sort {
+        SortOrder::Wasted | SortOrder::Size => {
+            missing.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)))
+        }
+        SortOrder::Count | SortOrder::Path => {}
+    }
+    let total_missing = missing.len();
+    let missing_bytes: u64 = missing.iter().map(|f| f.size).sum();
+    if let Some(top) = ranking.top.filter(|&top| top < missing.len()) {
+        print_info(&format!(
+            "Showing the top {} of {} files",
+            top,
+            missing.len()
+        ));
+        missing.truncate(top);
+    }
+
+    print_header("Files with no copy in the backups:\n");
+
+    for file in &missing {
+        let parent_dir = file
+            .path
+            .parent()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+        let filename = file
+            .path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| file.path.display().to_string());
+
+        println!(
+            "    {} Status: {} {}",
+            "📄".blue(),
+            "UNIQUE".red().bold(),
+            format!("({})", format_file_size(file.size)).dimmed()
+        );
+        println!("    {} Location: {}", "📍".yellow(), parent_dir.dimmed());
+        println!("    {} Title: {}", "🏷️".cyan(), filename.bold());
+        println!();
+    }
+
+    // Summary
+    println!();
+    print_header("Summary:");
+    print_info(&format!(
+        "Source files checked: {}",
+        comparison.source_files
+    ));
+    print_warning(&format!(
+        "Files with no backup: {} ({})",
+        total_missing,
+        format_file_size(missing_bytes)
+    ));
+
+    Ok(outcome)
+}
+
+fn handle_watch(
+    paths: &[PathBuf],
+    ndjson: bool,
+    action: Option<&Action>,
+    settle: Duration,
+    algorithm: HashAlgorithm,
+    journal: &mut Journal,
+) -> anyhow::Result<usize> {
+    // Absolute, so event paths match the scanned ones
+    let paths = paths
+        .iter()
+        .map(std::path::absolute)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut warnings = Vec::new();
+    let mut known = ContentSet::scan(&paths, algorithm, &mut warnings)?;
+    report_skipped(&warnings);
+    // Status goes to stderr so NDJSON output stays machine-readable
+    let status = format!(
+        "Watching {} files under {} (press Ctrl-C to stop)",
+        known.len(),
+        paths
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    if ndjson {
+        eprintln!("{}", status);
     } else {
-        handle_report_mode(duplicate_groups, &args.paths)?;
+        print_info(&status);
+    }
+
+    // Don't react to duplicates arriving in the move destination
+    let ignore: Vec<PathBuf> = match action {
+        Some(Action::Move(dir)) => vec![std::path::absolute(dir)?],
+        _ => Vec::new(),
+    };
+
+    watch(&paths, &ignore, settle, cancel_token(), |change| {
+        let path = match change {
+            Change::Updated(path) => path,
+            Change::Removed(path) => {
+                known.remove(&path);
+                return Ok(());
+            }
+            Change::Error(message) => {
+                eprintln!("Warning: Watch error: {}", message);
+                return Ok(());
+            }
+        };
+        let event = match known.check(&path) {
+            Ok(Some(event)) => event,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                eprintln!("Warning: Could not check {}: {}", path.display(), e);
+                return Ok(());
+            }
+        };
+
+        // Keep the copy that was there first
+        let result = action.map(|action| {
+            let mut file = FileInfo::new(event.path.clone(), event.size);
+            file.hash = Some(event.hash);
+            let mut keeper = FileInfo::new(event.duplicates[0].clone(), event.size);
+            keeper.hash = Some(event.hash);
+            let entry = apply_action(action, &file, &keeper)?;
+            journal.record(&entry)?;
+            known.remove(&event.path);
+            anyhow::Ok(action.kind())
+        });
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if ndjson {
+            let record = serde_json::json!({
+                "time": now,
+                "path": event.path.to_string_lossy(),
+                "size": event.size,
+                "hash": event.hash,
+                "duplicates": event
+                    .duplicates
+                    .iter()
+                    .map(|p| p.to_string_lossy())
+                    .collect::<Vec<_>>(),
+                "action": match &result {
+                    Some(Ok(kind)) => Some(kind.past_tense().to_string()),
+                    _ => None,
+                },
+                "error": match &result {
+                    Some(Err(e)) => Some(e.to_string()),
+                    _ => None,
+                },
+            });
+            println!("{}", record);
+        } else {
+            println!(
+                "{} {} {} {} {}",
+                format!("[{}]", format_timestamp(now)).dimmed(),
+                "Duplicate:".bold().magenta(),
+                event.path.display(),
+                format!("({})", format_file_size(event.size)).dimmed(),
+                format!(
+                    "matches {}",
+                    event
+                        .duplicates
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+                .dimmed()
+            );
+            match &result {
+                Some(Ok(kind)) => print_success(&format!(
+                    "{}: {}",
+                    capitalize(kind.past_tense()),
+                    event.path.display()
+                )),
+                Some(Err(e)) => print_error(&format!(
+                    "Failed to resolve {}: {}",
+                    event.path.display(),
+                    e
+                )),
+                None => {}
+            }
+        }
+        Ok(())
+    })?;
+    // Files the initial scan skipped count for --strict
+    Ok(warnings.len())
+}
+
+fn handle_index(
+    command: IndexCommand,
+    db: &Path,
+    algorithm: Option<HashAlgorithm>,
+) -> anyhow::Result<Outcome> {
+    let mut index = match algorithm {
+        Some(algorithm) => Index::open_with_algorithm(db, algorithm)?,
+        None => Index::open(db)?,
+    };
+
+    let mut outcome = Outcome::default();
+    match command {
+        IndexCommand::Build { root } => {
+            print_header(&format!("Indexing {}...", root.display()));
+            let stats = index.build(&root)?;
+            print_index_stats(&stats);
+            outcome.skipped += stats.failed + stats.warnings.len();
+        }
+        IndexCommand::Update { roots } => {
+            let roots = if roots.is_empty() {
+                index.roots()?
+            } else {
+                roots
+            };
+            if roots.is_empty() {
+                print_warning("The index has no roots yet; add one with `index build`");
+                return Ok(outcome);
+            }
+            for root in roots {
+                print_header(&format!("Updating {}...", root.display()));
+                let stats = index.update(&root)?;
+                print_index_stats(&stats);
+                outcome.skipped += stats.failed + stats.warnings.len();
+            }
+        }
+        IndexCommand::Query { files } => {
+            let mut found = 0;
+            for file in &files {
+                let matches = index.query(file)?;
+                if matches.is_empty() {
+                    println!(
+                        "    {} {} {}",
+                        "📄".blue(),
+                        file.display().to_string().bold(),
+                        "(no copy in the index)".dimmed()
+                    );
+                } else {
+                    found += 1;
+                    println!("    {} {}", "📄".blue(), file.display().to_string().bold());
+                    for path in matches {
+                        println!(
+                            "      {} {}",
+                            "↳".green(),
+                            path.display().to_string().dimmed()
+                        );
+                    }
+                }
+            }
+            println!();
+            print_info(&format!(
+                "{} of {} files are already stored",
+                found,
+                files.len()
+            ));
+            outcome.found = found > 0;
+        }
+    }
+
+    Ok(outcome)
+}
+
+fn print_index_stats(stats: &IndexStats) {
+    report_skipped(&stats.warnings);
+    print_success(&format!(
+        "Added: {}, updated: {}, unchanged: {}, removed: {}",
+        stats.added, stats.updated, stats.unchanged, stats.removed
+    ));
+    if stats.failed > 0 {
+        print_warning(&format!("Could not hash {} files", stats.failed));
+    }
+}
+
+fn handle_overlap(
+    paths: &[PathBuf],
+    order: OverlapOrder,
+    ranking: &RankingArgs,
+    scanning: &ScanArgs,
+    algorithm: HashAlgorithm,
+    verbose: bool,
+) -> anyhow::Result<Outcome> {
+    let result = scan_all(paths, scanning, algorithm, verbose)?;
+    let mut overlaps = find_overlaps(&result.groups);
+    let outcome = Outcome {
+        found: !overlaps.is_empty(),
+        skipped: result.warnings.len(),
+    };
+
+    if overlaps.is_empty() {
+        println!("No directories share duplicate files!");
+        return Ok(outcome);
+    }
+
+    sort_overlaps(&mut overlaps, order);
+    if let Some(top) = ranking.top.filter(|&top| top < overlaps.len()) {
+        print_info(&format!(
+            "Showing the top {} of {} directory pairs",
+            top,
+            overlaps.len()
+        ));
+        overlaps.truncate(top);
+    }
+
+    print_header("Directories sharing duplicate content:\n");
+
+    for (pair_idx, overlap) in overlaps.iter().enumerate() {
+        println!(
+            "{} {} {} {}",
+            "🔗".bold(),
+            "Directory Pair".bold().magenta(),
+            format!("{}/{}", pair_idx + 1, overlaps.len())
+                .bold()
+                .white(),
+            format!(
+                "(share {} across {} files)",
+                format_file_size(overlap.shared_bytes),
+                overlap.shared_files
+            )
+            .dimmed()
+        );
+        println!();
+        for (dir, ratio, total) in [
+            (&overlap.first, overlap.first_ratio(), overlap.first_total),
+            (
+                &overlap.second,
+                overlap.second_ratio(),
+                overlap.second_total,
+            ),
+        ] {
+            println!(
+                "    {} {} {}",
+                "📍".yellow(),
+                dir.display(),
+                format!("({:.0}% of {})", ratio * 100.0, format_file_size(total)).dimmed()
+            );
+        }
+        println!();
+    }
+
+    Ok(outcome)
+}
+
+/// Work through the pending groups of `session`, returning the number of
+/// files left out because they changed or the action failed on them
+fn handle_interactive_mode(
+    session: &mut Session,
+    session_path: &Path,
+    action: &Action,
+    journal: &mut Journal,
+) -> anyhow::Result<usize> {
+    let mut skipped = 0;
+    let total_groups = session.groups.len();
+    let pending = session.pending_count();
+    if pending < total_groups {
+        print_header(&format!(
+            "Resuming session: {} of {} duplicate groups left...",
+            pending, total_groups
+        ));
+    } else {
+        print_header(&format!(
+            "Found {} duplicate groups. Starting interactive resolution...",
+            total_groups
+        ));
     }
+    print_info(&format!(
+        "Progress is saved to {} (continue later with --resume)",
+        session_path.display()
+    ));
+    println!();
 
-    Ok(())
-}
-
-fn handle_report_mode(
-    duplicate_groups: Vec<DuplicateGroup>,
-    paths: &[PathBuf],
-) -> anyhow::Result<()> {
-    print_header("Found duplicate files:\n");
+    while let Some(group_idx) = session.next_pending() {
+        // Files may have changed since the session was saved
+        let Revalidated { group, dropped } = session.revalidate(group_idx)?;
 
-    let mut total_duplicate_files = 0;
+        print_duplicate_group_header(group_idx, total_groups, group.size, &group.hash);
+        println!();
+        for path in &dropped {
+            print_warning(&format!(
+                "Changed since the scan, leaving out: {}",
+                path.display()
+            ));
+        }
+        skipped += dropped.len();
 
-    for (group_idx, group) in duplicate_groups.iter().enumerate() {
-        total_duplicate_files += group.files.len();
+        let status = if group.files.len() < 2 {
+            print_info("No duplicates left in this group");
+            GroupStatus::Resolved
+        } else {
+            resolve_group(&group, group_idx, session, action, journal, &mut skipped)?
+        };
 
-        print_duplicate_group_header(group_idx, duplicate_groups.len(), group.size, &group.hash);
+        session.groups[group_idx].status = status;
+        session.save(session_path)?;
         println!();
 
-        for (i, file) in group.files.iter().enumerate() {
-            let marker = if i == 0 {
-                "KEEP".green().bold()
-            } else {
-                "DUP".red().bold()
-            };
-            let parent_dir = file
-                .path
-                .parent()
-                .map(|p| p.display().to_string())
-                .unwrap_or_else(|| "".to_string());
-            let filename = file
-                .path
-                .file_name()
-                .map(|f| f.to_string_lossy().to_string())
-                .unwrap_or_else(|| file.path.display().to_string());
-
-            println!("    {} Status: {}", "📄".blue(), marker);
-            println!("    {} Location: {}", "📍".yellow(), parent_dir.dimmed());
-            println!("    {} Title: {}", "🏷️".cyan(), filename.bold());
-            println!();
+        if cancel_token().is_cancelled() {
+            print_warning(&format!(
+                "Stopped after {} files {}; continue with --resume",
+                session.files_resolved,
+                action.kind().past_tense()
+            ));
+            print_info(&format!("Journal: {}", journal.path().display()));
+            return Err(Error::Cancelled.into());
         }
-        println!();
     }
 
-    // Summary
+    // Final summary
     println!();
-    print_header("Summary:");
+    print_success("Interactive deduplication complete!");
     print_info(&format!(
-        "Found {} duplicate groups",
-        duplicate_groups.len()
+        "Files {}: {}",
+        action.kind().past_tense(),
+        session.files_resolved
     ));
-    print_info(&format!("Total duplicate files: {}", total_duplicate_files));
-    print_warning(&format!(
-        "Files that could be removed: {}",
-        total_duplicate_files - duplicate_groups.len()
+    print_info(&format!("Journal: {}", journal.path().display()));
+    print_success(&format!(
+        "Space saved: {}",
+        format_file_size(session.bytes_saved)
     ));
 
-    // Calculate potential space savings
-    let mut potential_savings = 0u64;
-    for path in paths {
-        match collect_files_for_size_calc(path) {
-            Ok(files) => {
-                potential_savings =
-                    potential_savings.saturating_add(calculate_potential_savings(&files));
-            }
-            Err(e) => {
-                eprintln!(
-                    "Warning: Could not calculate savings for {}: {}",
-                    path.display(),
-                    e
-                );
-            }
+    if !session.rules.is_empty() {
+        print_header("Rules applied:");
+        for (rule, hits) in session.rules.iter().zip(&session.rule_hits) {
+            print_info(&format!("{} ({} groups)", rule, hits));
         }
     }
 
-    if potential_savings > 0 {
-        print_success(&format!(
-            "Potential space savings: {}",
-            format_file_size(potential_savings)
-        ));
-    }
-
-    Ok(())
+    Ok(skipped)
 }
 
-fn handle_interactive_mode(duplicate_groups: Vec<DuplicateGroup>) -> anyhow::Result<()> {
-    print_header(&format!(
-        "Found {} duplicate groups. Starting interactive resolution...",
-        duplicate_groups.len()
-    ));
-    println!();
-
-    let mut total_deleted = 0;
-    let mut total_space_saved = 0u64;
-
-    for (group_idx, group) in duplicate_groups.iter().enumerate() {
-        print_duplicate_group_header(group_idx, duplicate_groups.len(), group.size, &group.hash);
-        println!();
+/// Ask what to do with one duplicate group and carry it out
+///
+/// Files the action fails on are added to `skipped`.
+fn resolve_group(
+    group: &DuplicateGroup,
+    group_idx: usize,
+    session: &mut Session,
+    action: &Action,
+    journal: &mut Journal,
+    skipped: &mut usize,
+) -> anyhow::Result<GroupStatus> {
+    let mut displayed = false;
+    loop {
+        // Remembered decisions take over before asking again
+        if let Some((rule_idx, decision)) = first_match(&session.rules, group) {
+            session.rule_hits[rule_idx] += 1;
+            print_info(&format!("Applying rule: {}", session.rules[rule_idx]));
+            return match decision {
+                RuleDecision::Skip => {
+                    print_warning(&format!("Skipping group {}", group_idx + 1));
+                    Ok(GroupStatus::Skipped)
+                }
+                RuleDecision::Resolve { keeper, files } => {
+                    let resolution = resolve_files(&files, keeper, action, journal)?;
+                    *skipped += resolution.failed;
+                    Ok(session.record_resolved(group_idx, &resolution.done, resolution.stopped))
+                }
+            };
+        }
 
-        // Display all files in the group
-        for (i, file) in group.files.iter().enumerate() {
-            println!("  {} {}:", "📄".blue(), format!("{}", i + 1).bold().white());
-            println!(
-                "    {} {}",
-                "📍".yellow(),
-                file.path
-                    .parent()
-                    .map(|p| p.display().to_string())
-                    .unwrap_or_else(|| "/".to_string())
-                    .dimmed()
-            );
-            println!(
-                "    {} {}",
-                "🏷️".cyan(),
-                file.path
-                    .file_name()
-                    .map(|f| f.to_string_lossy().to_string())
-                    .unwrap_or_else(|| file.path.display().to_string())
-                    .bold()
-            );
-            println!();
+        if !displayed {
+            display_group_files(group);
+            displayed = true;
         }
-        println!();
 
         // Ask user what to do with this group
         let options = vec![
             "Select files to keep (others will be deleted)",
             "Skip this group (no deletions)",
             "Keep first file, delete all others",
+            "Always keep the copies under a directory (this and remaining groups)",
+            "Always skip groups with a copy under a directory",
+            "Preview file contents",
         ];
 
         let selection = Select::new()
@@ -216,97 +1443,314 @@ fn handle_interactive_mode(duplicate_groups: Vec<DuplicateGroup>) -> anyhow::Res
             0 => {
                 // Interactive selection
                 let files_to_delete = select_files_to_delete(&group.files)?;
-                if !files_to_delete.is_empty() && confirm_deletion(&files_to_delete)? {
-                    let deleted_count = delete_files(&files_to_delete)?;
-                    total_deleted += deleted_count;
-                    total_space_saved = total_space_saved
-                        .saturating_add(group.size.saturating_mul(deleted_count as u64));
+                if !files_to_delete.is_empty() && confirm_deletion(&files_to_delete, action)? {
+                    let keeper = keeper_for(&group.files, &files_to_delete);
+                    let resolution = resolve_files(&files_to_delete, keeper, action, journal)?;
+                    *skipped += resolution.failed;
+                    return Ok(session.record_resolved(
+                        group_idx,
+                        &resolution.done,
+                        resolution.stopped,
+                    ));
                 }
+                return Ok(GroupStatus::Skipped);
             }
             1 => {
                 // Skip this group
                 print_warning(&format!("Skipping group {}", group_idx + 1));
-                println!();
-                continue;
+                return Ok(GroupStatus::Skipped);
             }
             2 => {
                 // Keep first, delete others
                 let files_to_delete: Vec<_> = group.files.iter().skip(1).collect();
-                if !files_to_delete.is_empty() && confirm_deletion(&files_to_delete)? {
-                    let deleted_count = delete_files(&files_to_delete)?;
-                    total_deleted += deleted_count;
-                    total_space_saved = total_space_saved
-                        .saturating_add(group.size.saturating_mul(deleted_count as u64));
+                if !files_to_delete.is_empty() && confirm_deletion(&files_to_delete, action)? {
+                    let resolution =
+                        resolve_files(&files_to_delete, &group.files[0], action, journal)?;
+                    *skipped += resolution.failed;
+                    return Ok(session.record_resolved(
+                        group_idx,
+                        &resolution.done,
+                        resolution.stopped,
+                    ));
+                }
+                return Ok(GroupStatus::Skipped);
+            }
+            3 | 4 => {
+                // Turn the decision into a rule, then let the rule handle this group
+                let dir = choose_directory(&group.files)?;
+                let rule = if selection == 3 {
+                    Rule::KeepUnder(dir)
+                } else {
+                    Rule::SkipUnder(dir)
+                };
+                if rule.decide(group).is_none() {
+                    print_error("No copy of this group is under that directory");
+                    continue;
+                }
+                if Confirm::new()
+                    .with_prompt(format!(
+                        "{} Apply \"{}\" automatically to this and all remaining groups?",
+                        "⚠️".yellow(),
+                        rule
+                    ))
+                    .default(false)
+                    .interact()?
+                {
+                    session.rules.push(rule);
+                    session.rule_hits.push(0);
                 }
             }
+            5 => display_preview(group),
             _ => unreachable!(),
         }
+    }
+}
 
+fn display_group_files(group: &DuplicateGroup) {
+    for (i, file) in group.files.iter().enumerate() {
+        println!("  {} {}:", "📄".blue(), format!("{}", i + 1).bold().white());
+        println!(
+            "    {} {}",
+            "📍".yellow(),
+            file.path
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "/".to_string())
+                .dimmed()
+        );
+        println!(
+            "    {} {}",
+            "🏷️".cyan(),
+            file.path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_else(|| file.path.display().to_string())
+                .bold()
+        );
+        match file_details(&file.path) {
+            Ok(details) => println!(
+                "    {} {}",
+                "🕒".magenta(),
+                format_details(&details).dimmed()
+            ),
+            Err(e) => print_warning(&format!("Cannot read metadata: {}", e)),
+        }
         println!();
     }
+    println!();
+}
 
-    // Final summary
+/// One-line summary of the metadata that tells copies apart
+fn format_details(details: &FileDetails) -> String {
+    let mut parts = vec![details
+        .modified
+        .map(format_timestamp)
+        .unwrap_or_else(|| "unknown time".to_string())];
+    parts.push(details.permissions.clone());
+    if let Some(owner) = &details.owner {
+        parts.push(owner.clone());
+    }
+    if let Some(links) = details.links {
+        parts.push(format!(
+            "{} link{}",
+            links,
+            if links == 1 { "" } else { "s" }
+        ));
+    }
+    parts.join("  ")
+}
+
+/// Show the start of the content shared by every file in `group`
+fn display_preview(group: &DuplicateGroup) {
+    let Some(file) = group.files.first() else {
+        return;
+    };
     println!();
-    print_success("Interactive deduplication complete!");
-    print_info(&format!("Files deleted: {}", total_deleted));
+    print_info(&format!(
+        "All {} copies have this content (from {}):",
+        group.files.len(),
+        file.path.display()
+    ));
+    match preview(&file.path, PREVIEW_LINES) {
+        Ok(Preview::Text(lines)) if lines.is_empty() => println!("    {}", "(empty)".dimmed()),
+        Ok(Preview::Text(lines)) | Ok(Preview::Binary(lines)) => {
+            for line in lines {
+                println!("    {}", line.dimmed());
+            }
+        }
+        Err(e) => print_error(&format!("Cannot preview {}: {}", file.path.display(), e)),
+    }
+    println!();
+}
+
+/// Let the user pick one of the directories holding `files`, or type another
+fn choose_directory(files: &[FileInfo]) -> anyhow::Result<PathBuf> {
+    let mut dirs: Vec<&Path> = files.iter().filter_map(|f| f.path.parent()).collect();
+    dirs.sort();
+    dirs.dedup();
+
+    let mut labels: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+    labels.push("Other directory...".to_string());
+
+    let choice = Select::new()
+        .with_prompt("Directory")
+        .items(&labels)
+        .default(0)
+        .interact()?;
+
+    match dirs.get(choice) {
+        Some(dir) => Ok(dir.to_path_buf()),
+        None => {
+            let dir: String = Input::new().with_prompt("Directory").interact_text()?;
+            // Session paths are absolute, so `./photos` has to be too
+            Ok(std::path::absolute(dir)?)
+        }
+    }
+}
+
+/// Browse the groups and carry out the decisions, returning the number of
+/// files the action failed on
+fn handle_tui_mode(
+    duplicate_groups: Vec<DuplicateGroup>,
+    action: &Action,
+    journal: &mut Journal,
+) -> anyhow::Result<usize> {
+    let Some(decisions) = tui::run(&duplicate_groups, action)? else {
+        print_warning("Quit without changing any files");
+        return Ok(0);
+    };
+
+    let mut total_deleted = 0;
+    let mut total_space_saved = 0u64;
+    let mut skipped = 0;
+
+    for decision in decisions {
+        let group = &duplicate_groups[decision.group];
+        let files_to_delete: Vec<_> = decision.files.iter().map(|&i| &group.files[i]).collect();
+        let keeper = keeper_for(&group.files, &files_to_delete);
+        let resolution = resolve_files(&files_to_delete, keeper, action, journal)?;
+        let done = resolution.done.len();
+        total_deleted += done;
+        skipped += resolution.failed;
+        total_space_saved =
+            total_space_saved.saturating_add(group.size.saturating_mul(done as u64));
+    }
+
+    println!();
+    let cancelled = cancel_token().is_cancelled();
+    if cancelled {
+        print_warning("Stopped before every selected file was handled");
+    } else {
+        print_success("Deduplication complete!");
+    }
+    print_info(&format!(
+        "Files {}: {}",
+        action.kind().past_tense(),
+        total_deleted
+    ));
+    print_info(&format!("Journal: {}", journal.path().display()));
     print_success(&format!(
         "Space saved: {}",
         format_file_size(total_space_saved)
     ));
+    if cancelled {
+        return Err(Error::Cancelled.into());
+    }
 
-    Ok(())
+    Ok(skipped)
 }
 
 fn select_files_to_delete(files: &[FileInfo]) -> anyhow::Result<Vec<&FileInfo>> {
-    let mut files_to_delete = Vec::new();
+    let mtimes: Vec<Option<u64>> = files.iter().map(|f| modification_time(&f.path)).collect();
 
-    print_warning("Select files to DELETE (you must keep at least one file):");
-    println!();
+    let presets = vec![
+        "Start with nothing selected",
+        "Select all except the newest copy",
+        "Select all except the oldest copy",
+        "Select all under a directory...",
+    ];
+    let preset = Select::new()
+        .with_prompt(format!("{} Preselect files to DELETE", "🗑️".red()))
+        .items(&presets)
+        .default(0)
+        .interact()?;
 
-    for file in files.iter() {
-        let parent_dir = file
-            .path
-            .parent()
-            .map(|p| p.display().to_string())
-            .unwrap_or_else(|| "".to_string());
-        let filename = file
-            .path
-            .file_name()
-            .map(|f| f.to_string_lossy().to_string())
-            .unwrap_or_else(|| file.path.display().to_string());
+    let mut selected = match preset {
+        1 => all_except(files.len(), pick_by_mtime(&mtimes, |a, b| a > b)),
+        2 => all_except(files.len(), pick_by_mtime(&mtimes, |a, b| a < b)),
+        3 => {
+            let dir = choose_directory(files)?;
+            files.iter().map(|f| f.path.starts_with(&dir)).collect()
+        }
+        _ => vec![false; files.len()],
+    };
 
-        let prompt = format!(
-            "{} Delete: {}/{}",
-            "🗑️".red(),
-            parent_dir.dimmed(),
-            filename.bold()
-        );
+    let items: Vec<String> = files
+        .iter()
+        .zip(&mtimes)
+        .map(|(file, mtime)| {
+            format!(
+                "{}  {}  {}",
+                file.path.display(),
+                format_file_size(file.size).dimmed(),
+                mtime
+                    .map(format_timestamp)
+                    .unwrap_or_else(|| "unknown mtime".to_string())
+                    .dimmed()
+            )
+        })
+        .collect();
 
-        if Confirm::new()
-            .with_prompt(&prompt)
-            .default(false)
-            .interact()?
-        {
-            files_to_delete.push(file);
+    loop {
+        let chosen = MultiSelect::new()
+            .with_prompt(format!(
+                "{} Select files to DELETE (space toggles, enter confirms; keep at least one)",
+                "🗑️".red()
+            ))
+            .items(&items)
+            .defaults(&selected)
+            .interact()?;
+
+        // Ensure at least one file is kept
+        if chosen.len() >= files.len() {
+            print_error("You must keep at least one file from each duplicate group!");
+            selected = vec![true; files.len()];
+            continue;
         }
+
+        return Ok(chosen.into_iter().map(|i| &files[i]).collect());
     }
+}
 
-    // Ensure at least one file is kept
-    if files_to_delete.len() >= files.len() {
-        print_error("You must keep at least one file from each duplicate group!");
-        return Ok(Vec::new());
+/// Index of the file whose mtime wins under `better`, ignoring unknown mtimes
+fn pick_by_mtime(mtimes: &[Option<u64>], better: impl Fn(u64, u64) -> bool) -> Option<usize> {
+    let mut best: Option<(usize, u64)> = None;
+    for (i, mtime) in mtimes.iter().enumerate() {
+        if let Some(mtime) = *mtime {
+            if best.is_none_or(|(_, b)| better(mtime, b)) {
+                best = Some((i, mtime));
+            }
+        }
     }
+    best.map(|(i, _)| i)
+}
 
-    Ok(files_to_delete)
+fn all_except(len: usize, keep: Option<usize>) -> Vec<bool> {
+    // Without a known mtime keep the first file rather than selecting everything
+    let keep = keep.unwrap_or(0);
+    (0..len).map(|i| i != keep).collect()
 }
 
-fn confirm_deletion(files_to_delete: &[&FileInfo]) -> anyhow::Result<bool> {
+fn confirm_deletion(files_to_delete: &[&FileInfo], action: &Action) -> anyhow::Result<bool> {
     if files_to_delete.is_empty() {
         return Ok(false);
     }
 
     println!();
-    print_warning("Files selected for deletion:");
+    print_warning(&match action {
+        Action::Delete => "Files selected for deletion:".to_string(),
+        _ => format!("Files selected to {}:", action.kind()),
+    });
     for file in files_to_delete {
         println!(
             "  {} {}",
@@ -316,49 +1760,283 @@ fn confirm_deletion(files_to_delete: &[&FileInfo]) -> anyhow::Result<bool> {
     }
     println!();
 
+    let prompt = match action {
+        Action::Delete => {
+            "Are you sure you want to delete these files? This action cannot be undone!".to_string()
+        }
+        _ => format!("Are you sure you want to {} these files?", action.kind()),
+    };
+
     Confirm::new()
-        .with_prompt(format!(
-            "{} Are you sure you want to delete these files? This action cannot be undone!",
-            "⚠️".red().bold()
-        ))
+        .with_prompt(format!("{} {}", "⚠️".red().bold(), prompt))
         .default(false)
         .interact()
         .map_err(|e| anyhow::anyhow!("Failed to get confirmation: {}", e))
 }
 
-fn delete_files(files_to_delete: &[&FileInfo]) -> anyhow::Result<usize> {
-    let mut deleted_count = 0;
+/// The file that survives once `files_to_delete` are resolved
+fn keeper_for<'a>(files: &'a [FileInfo], files_to_delete: &[&FileInfo]) -> &'a FileInfo {
+    files
+        .iter()
+        .find(|f| !files_to_delete.iter().any(|d| d.path == f.path))
+        .expect("at least one file is always kept")
+}
+
+/// What [`resolve_files`] did with the files it was given
+#[derive(Default)]
+struct Resolution {
+    /// Files the action was applied to
+    done: Vec<PathBuf>,
+    /// Files left alone because the action failed or was refused
+    failed: usize,
+    /// Whether Ctrl-C stopped it before every file was tried
+    stopped: bool,
+}
+
+fn resolve_files(
+    files_to_delete: &[&FileInfo],
+    keeper: &FileInfo,
+    action: &Action,
+    journal: &mut Journal,
+) -> anyhow::Result<Resolution> {
+    let mut resolution = Resolution::default();
 
     for file in files_to_delete {
-        // Verify file still exists and has expected size (TOCTOU protection)
-        if let Ok(metadata) = fs::metadata(&file.path) {
-            if metadata.len() != file.size {
-                print_error(&format!(
-                    "File {} changed size, skipping deletion",
+        // Finish the file at hand, but don't start another
+        if cancel_token().is_cancelled() {
+            resolution.stopped = true;
+            break;
+        }
+        match apply_action(action, file, keeper) {
+            Ok(entry) => {
+                journal.record(&entry)?;
+                print_success(&format!(
+                    "{}: {}",
+                    capitalize(action.kind().past_tense()),
                     file.path.display()
                 ));
-                continue;
+                resolution.done.push(file.path.clone());
             }
-        } else {
-            print_error(&format!(
-                "File {} no longer exists, skipping",
-                file.path.display()
+            Err(e) => {
+                print_error(&format!("Skipping {}: {}", file.path.display(), e));
+                resolution.failed += 1;
+            }
+        }
+    }
+
+    Ok(resolution)
+}
+
+fn handle_plan(
+    duplicate_groups: &[DuplicateGroup],
+    action: &Action,
+    output: &Path,
+) -> anyhow::Result<()> {
+    let plan = Plan::from_groups(duplicate_groups, action);
+    plan.save(output)?;
+
+    let planned = plan
+        .groups
+        .iter()
+        .flat_map(|g| &g.files)
+        .filter(|f| f.action != PlannedAction::Keep)
+        .count();
+    print_success(&format!(
+        "Wrote plan for {} duplicate groups ({} files to {}) to {}",
+        plan.groups.len(),
+        planned,
+        action.kind(),
+        output.display()
+    ));
+    print_info("Review and edit the plan, then run it with `file-dedup apply`");
+
+    Ok(())
+}
+
+fn handle_script(
+    duplicate_groups: &[DuplicateGroup],
+    action: &Action,
+    output: &Path,
+) -> anyhow::Result<()> {
+    let mut script = Vec::new();
+    write_script(duplicate_groups, action, &mut script)?;
+    fs::write(output, script)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let duplicates: usize = duplicate_groups.iter().map(|g| g.files.len() - 1).sum();
+    print_success(&format!(
+        "Wrote script for {} duplicate groups ({} files to {}) to {}",
+        duplicate_groups.len(),
+        duplicates,
+        action.kind(),
+        output.display()
+    ));
+    print_info(&format!(
+        "Review it, then run it with: sh {}",
+        output.display()
+    ));
+
+    Ok(())
+}
+
+/// Carry out the plan, returning the number of actions that were refused
+/// or failed
+fn handle_apply(plan_path: &Path, journal: &mut Journal) -> anyhow::Result<usize> {
+    let plan = Plan::load(plan_path)?;
+    print_header(&format!(
+        "Applying plan {} ({} groups)",
+        plan_path.display(),
+        plan.groups.len()
+    ));
+    println!();
+
+    let outcomes = apply_plan(&plan, journal, cancel_token())?;
+    let mut applied = 0;
+    let mut skipped = 0;
+
+    for outcome in &outcomes {
+        match &outcome.result {
+            Ok(()) => {
+                print_success(&format!(
+                    "{}: {}",
+                    capitalize(outcome.action.past_tense()),
+                    outcome.path.display()
+                ));
+                applied += 1;
+            }
+            Err(e) => {
+                print_error(&format!("Skipping {}: {}", outcome.path.display(), e));
+                skipped += 1;
+            }
+        }
+    }
+
+    println!();
+    print_info(&format!("Actions applied: {}", applied));
+    if skipped > 0 {
+        print_warning(&format!("Actions skipped: {}", skipped));
+    }
+    print_info(&format!("Journal: {}", journal.path().display()));
+
+    if cancel_token().is_cancelled() {
+        let planned = plan.groups.iter().flat_map(|g| &g.files);
+        let not_attempted = planned
+            .filter(|f| f.action != PlannedAction::Keep)
+            .count()
+            .saturating_sub(outcomes.len());
+        print_warning(&format!("Stopped with {} actions left", not_attempted));
+        return Err(Error::Cancelled.into());
+    }
+
+    Ok(skipped)
+}
+
+/// Undo the latest journal entries, returning the number that could not be
+/// undone
+fn handle_undo(journal_path: &Path, last: Option<usize>, dry_run: bool) -> anyhow::Result<usize> {
+    if !journal_path.exists() {
+        println!("No journal found at {}", journal_path.display());
+        return Ok(0);
+    }
+
+    let entries = Journal::read_entries(journal_path)?;
+    let mut pending = pending_operations(&entries);
+    if let Some(last) = last {
+        pending.truncate(last);
+    }
+
+    if pending.is_empty() {
+        println!("Nothing to undo.");
+        return Ok(0);
+    }
+
+    print_header(&format!(
+        "Undoing {} operations from {}",
+        pending.len(),
+        journal_path.display()
+    ));
+    println!();
+
+    let mut journal = if dry_run {
+        None
+    } else {
+        Some(Journal::open(journal_path)?)
+    };
+    let mut restored = 0;
+    let mut manual = 0;
+    let mut failed = 0;
+
+    for entry in pending {
+        if cancel_token().is_cancelled() {
+            break;
+        }
+        if dry_run {
+            print_info(&format!(
+                "Would undo {}: {}",
+                entry.action,
+                entry.path.display()
             ));
             continue;
         }
 
-        match fs::remove_file(&file.path) {
-            Ok(()) => {
-                print_success(&format!("Deleted: {}", file.path.display()));
-                deleted_count += 1;
+        match undo_entry(entry) {
+            Ok(UndoOutcome::Restored) => {
+                if let Some(journal) = journal.as_mut() {
+                    journal.record(&entry.reversed())?;
+                }
+                print_success(&format!("Restored: {}", entry.path.display()));
+                restored += 1;
+            }
+            Ok(UndoOutcome::Recreated) => {
+                if let Some(journal) = journal.as_mut() {
+                    journal.record(&entry.reversed())?;
+                }
+                print_info(&format!("Already back in place: {}", entry.path.display()));
+            }
+            Ok(UndoOutcome::CopyFromKeeper {
+                keeper,
+                keeper_intact,
+            }) => {
+                manual += 1;
+                if keeper_intact {
+                    print_warning(&format!(
+                        "{} was deleted; recreate it by copying {}",
+                        entry.path.display(),
+                        keeper.display()
+                    ));
+                } else {
+                    print_error(&format!(
+                        "{} was deleted and its keeper {} is missing or changed",
+                        entry.path.display(),
+                        keeper.display()
+                    ));
+                }
             }
             Err(e) => {
-                print_error(&format!("Failed to delete {}: {}", file.path.display(), e));
+                print_error(&format!("Could not undo {}: {}", entry.path.display(), e));
+                failed += 1;
             }
         }
     }
 
-    Ok(deleted_count)
+    if !dry_run {
+        println!();
+        print_info(&format!("Files restored: {}", restored));
+        if manual > 0 {
+            print_warning(&format!("Deleted files needing a manual copy: {}", manual));
+        }
+    }
+    if cancel_token().is_cancelled() {
+        return Err(Error::Cancelled.into());
+    }
+
+    // Deleted files waiting for a manual copy weren't undone either
+    Ok(manual + failed)
 }
 
 // Pretty printing helper functions
@@ -379,6 +2057,40 @@ fn format_file_size(bytes: u64) -> String {
     }
 }
 
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Format seconds since the Unix epoch as a UTC date and time
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3_600,
+        time % 3_600 / 60
+    )
+}
+
 fn print_header(text: &str) {
     println!("{} {}", "🔍".blue(), text.bold().blue());
 }
@@ -399,13 +2111,13 @@ fn print_info(text: &str) {
     println!("{} {}", "ℹ️".cyan(), text.cyan());
 }
 
-fn print_duplicate_group_header(group_idx: usize, total_groups: usize, size: u64, hash: &str) {
+fn print_duplicate_group_header(group_idx: usize, total_groups: usize, size: u64, hash: &Digest) {
     println!(
         "{} {} {} {} {}",
         "📁".bold(),
         "Duplicate Group".bold().magenta(),
         format!("{}/{}", group_idx + 1, total_groups).bold().white(),
         format!("({})", format_file_size(size)).dimmed(),
-        format!("Hash: {}", hash.get(..8).unwrap_or(hash)).dimmed()
+        format!("Hash: {}", &hash.to_hex()[..8]).dimmed()
     );
 }
@@ -0,0 +1,168 @@
+//! Directory overlap analysis
+//!
+//! Aggregates duplicate groups by the pair of directories their copies live
+//! in, to show which folders share the most content. Overlap is given both in
+//! bytes and relative to each directory's total size, so a folder that is
+//! entirely contained in another stands out even when it is small.
+
+use crate::vfs::{FileSystem, FileType, LocalFs};
+use crate::DuplicateGroup;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Duplicate content shared by two directories
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirOverlap {
+    pub first: PathBuf,
+    pub second: PathBuf,
+    /// Number of distinct contents present in both directories
+    pub shared_files: usize,
+    /// Size of one copy of every shared content
+    pub shared_bytes: u64,
+    /// Total size of the files directly inside `first` and `second`
+    pub first_total: u64,
+    pub second_total: u64,
+}
+
+impl DirOverlap {
+    /// Share of `first` that also exists in `second`, from 0.0 to 1.0
+    pub fn first_ratio(&self) -> f64 {
+        ratio(self.shared_bytes, self.first_total)
+    }
+
+    /// Share of `second` that also exists in `first`, from 0.0 to 1.0
+    pub fn second_ratio(&self) -> f64 {
+        ratio(self.shared_bytes, self.second_total)
+    }
+
+    /// The larger of the two ratios
+    pub fn max_ratio(&self) -> f64 {
+        self.first_ratio().max(self.second_ratio())
+    }
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (part as f64 / total as f64).min(1.0)
+    }
+}
+
+/// How directory pairs are ranked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverlapOrder {
+    /// Most shared bytes first
+    Bytes,
+    /// Highest overlap relative to either directory's size first
+    Percent,
+}
+
+/// Pair up the directories holding copies of each group
+pub fn find_overlaps(groups: &[DuplicateGroup]) -> Vec<DirOverlap> {
+    find_overlaps_in(&LocalFs, groups)
+}
+
+/// Like [`find_overlaps`], taking directory sizes from `fs`, which must be
+/// the filesystem the groups were scanned from
+pub fn find_overlaps_in(fs: &dyn FileSystem, groups: &[DuplicateGroup]) -> Vec<DirOverlap> {
+    let mut shared: HashMap<(&Path, &Path), (usize, u64)> = HashMap::new();
+
+    for group in groups {
+        let dirs: BTreeSet<&Path> = group.files.iter().filter_map(|f| f.path.parent()).collect();
+        let dirs: Vec<&Path> = dirs.into_iter().collect();
+        for (i, first) in dirs.iter().enumerate() {
+            for second in &dirs[i + 1..] {
+                let entry = shared.entry((first, second)).or_default();
+                entry.0 += 1;
+                entry.1 = entry.1.saturating_add(group.size);
+            }
+        }
+    }
+
+    let mut totals: HashMap<&Path, u64> = HashMap::new();
+    let mut overlaps: Vec<DirOverlap> = shared
+        .into_iter()
+        .map(
+            |((first, second), (shared_files, shared_bytes))| DirOverlap {
+                first: first.to_path_buf(),
+                second: second.to_path_buf(),
+                shared_files,
+                shared_bytes,
+                first_total: *totals.entry(first).or_insert_with(|| dir_size(fs, first)),
+                second_total: *totals.entry(second).or_insert_with(|| dir_size(fs, second)),
+            },
+        )
+        .collect();
+
+    sort_overlaps(&mut overlaps, OverlapOrder::Bytes);
+    overlaps
+}
+
+pub fn sort_overlaps(overlaps: &mut [DirOverlap], order: OverlapOrder) {
+    overlaps.sort_by(|a, b| {
+        let primary = match order {
+            OverlapOrder::Bytes => b.shared_bytes.cmp(&a.shared_bytes),
+            OverlapOrder::Percent => b.max_ratio().total_cmp(&a.max_ratio()),
+        };
+        primary
+            .then_with(|| a.first.cmp(&b.first))
+            .then_with(|| a.second.cmp(&b.second))
+    });
+}
+
+/// Total size of the regular files directly inside `dir`
+fn dir_size(fs: &dyn FileSystem, dir: &Path) -> u64 {
+    let Ok(entries) = fs.read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .iter()
+        .filter_map(|entry| fs.symlink_metadata(entry).ok())
+        // Symlinks are skipped, as in the scan
+        .filter(|metadata| metadata.file_type == FileType::File)
+        .map(|metadata| metadata.len)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+    use crate::test_util::write_file;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_overlaps_rank_by_bytes_and_percent() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        // downloads shares two large files with pictures
+        write_file(root, "downloads/a.jpg", b"aaaaaaaaaa");
+        write_file(root, "downloads/b.jpg", b"bbbbbbbbbb");
+        write_file(root, "downloads/other.zip", b"zzzzzzzzzzzzzzzzzzzz");
+        write_file(root, "pictures/a.jpg", b"aaaaaaaaaa");
+        write_file(root, "pictures/b.jpg", b"bbbbbbbbbb");
+        write_file(root, "pictures/more.png", b"pppppppppppppppppppppppppppppp");
+        // tiny is a small folder entirely contained in downloads
+        write_file(root, "tiny/z.zip", b"zzzzzzzzzzzzzzzzzzzz");
+
+        let groups = Scanner::new().root(root).scan().unwrap().groups;
+
+        let mut overlaps = find_overlaps(&groups);
+        assert_eq!(overlaps.len(), 2);
+
+        let top = &overlaps[0];
+        assert_eq!(
+            (top.first.clone(), top.second.clone()),
+            (root.join("downloads"), root.join("pictures"))
+        );
+        assert_eq!(top.shared_files, 2);
+        assert_eq!(top.shared_bytes, 20);
+        assert_eq!(top.first_total, 40);
+        assert!((top.first_ratio() - 0.5).abs() < 1e-9);
+
+        sort_overlaps(&mut overlaps, OverlapOrder::Percent);
+        assert_eq!(overlaps[0].second, root.join("tiny"));
+        assert!((overlaps[0].max_ratio() - 1.0).abs() < 1e-9);
+    }
+}
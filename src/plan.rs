@@ -0,0 +1,280 @@
+//! Reviewable deletion plans
+//!
+//! A plan lists every duplicate group together with the action chosen for
+//! each file. It is written as JSON so it can be reviewed and edited before
+//! being applied. Each file records its size, modification time and hash at
+//! planning time; anything that changed since is skipped when applying.
+
+use crate::actions::{apply_action, Action, ActionKind};
+use crate::cancel::CancellationToken;
+use crate::hash::HashAlgorithm;
+use crate::journal::Journal;
+use crate::{modification_time, DuplicateGroup, FileInfo};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Current plan file format version
+pub const PLAN_VERSION: u32 = 1;
+
+/// What to do with a single file of a planned group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlannedAction {
+    Keep,
+    Delete,
+    Hardlink,
+    Symlink,
+    Move,
+}
+
+impl PlannedAction {
+    /// The action applied to the file, or `None` if it is kept
+    pub fn kind(self) -> Option<ActionKind> {
+        match self {
+            PlannedAction::Keep => None,
+            PlannedAction::Delete => Some(ActionKind::Delete),
+            PlannedAction::Hardlink => Some(ActionKind::Hardlink),
+            PlannedAction::Symlink => Some(ActionKind::Symlink),
+            PlannedAction::Move => Some(ActionKind::Move),
+        }
+    }
+}
+
+impl From<ActionKind> for PlannedAction {
+    fn from(kind: ActionKind) -> Self {
+        match kind {
+            ActionKind::Delete => PlannedAction::Delete,
+            ActionKind::Hardlink => PlannedAction::Hardlink,
+            ActionKind::Symlink => PlannedAction::Symlink,
+            ActionKind::Move => PlannedAction::Move,
+        }
+    }
+}
+
+/// A file as it was seen when the plan was made
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub path: PathBuf,
+    pub action: PlannedAction,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: u64,
+}
+
+/// One duplicate group of a plan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanGroup {
+    pub size: u64,
+    pub hash: String,
+    /// Algorithm of `hash`
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_default")]
+    pub algorithm: HashAlgorithm,
+    pub files: Vec<PlannedFile>,
+}
+
+/// A complete plan, as written by `plan` and read by `apply`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    /// Directory that files planned for `move` are moved into
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub move_to: Option<PathBuf>,
+    pub groups: Vec<PlanGroup>,
+}
+
+impl Plan {
+    /// Build a plan that keeps the first file of every group and applies
+    /// `action` to the others
+    pub fn from_groups(groups: &[DuplicateGroup], action: &Action) -> Self {
+        // Absolute like the file paths, so applying from elsewhere moves the
+        // files to the same place
+        let move_to = match action {
+            Action::Move(dir) => Some(std::path::absolute(dir).unwrap_or_else(|_| dir.clone())),
+            _ => None,
+        };
+
+        let groups = groups
+            .iter()
+            .map(|group| PlanGroup {
+                size: group.size,
+                hash: group.hash.to_hex(),
+                algorithm: group.hash.algorithm(),
+                files: group
+                    .files
+                    .iter()
+                    .enumerate()
+                    .map(|(i, file)| PlannedFile {
+                        // Absolute, so the plan can be applied from anywhere
+                        path: std::path::absolute(&file.path).unwrap_or_else(|_| file.path.clone()),
+                        action: if i == 0 {
+                            PlannedAction::Keep
+                        } else {
+                            action.kind().into()
+                        },
+                        mtime: modification_time(&file.path).unwrap_or(0),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            version: PLAN_VERSION,
+            move_to,
+            groups,
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let plan: Plan = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if plan.version != PLAN_VERSION {
+            anyhow::bail!("Unsupported plan version {}", plan.version);
+        }
+        Ok(plan)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+/// What happened to a single planned file when the plan was applied
+#[derive(Debug)]
+pub struct PlanOutcome {
+    pub path: PathBuf,
+    pub action: ActionKind,
+    /// `Err` holds the reason the file was skipped
+    pub result: anyhow::Result<()>,
+}
+
+/// Execute every non-keep action of `plan`, recording each change in `journal`
+///
+/// Before touching a file, its size, modification time and hash are compared
+/// with the plan, and files that changed are skipped. A group is skipped as a
+/// whole if it has no file to keep or the kept file changed.
+///
+/// Once `cancel` is cancelled no further file is touched, and the outcomes of
+/// the files handled so far are returned.
+pub fn apply_plan(
+    plan: &Plan,
+    journal: &mut Journal,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<PlanOutcome>> {
+    let mut outcomes = Vec::new();
+
+    for group in &plan.groups {
+        let planned: Vec<_> = group
+            .files
+            .iter()
+            .filter_map(|f| f.action.kind().map(|kind| (f, kind)))
+            .collect();
+        if planned.is_empty() {
+            continue;
+        }
+
+        let keeper = group
+            .files
+            .iter()
+            .find(|f| f.action == PlannedAction::Keep)
+            .ok_or_else(|| anyhow::anyhow!("Group has no file marked keep"))
+            .and_then(|f| verify_planned_file(f, group));
+
+        let keeper = match keeper {
+            Ok(keeper) => keeper,
+            Err(e) => {
+                for (file, kind) in planned {
+                    outcomes.push(PlanOutcome {
+                        path: file.path.clone(),
+                        action: kind,
+                        result: Err(anyhow::anyhow!("keeper rejected: {}", e)),
+                    });
+                }
+                continue;
+            }
+        };
+
+        for (file, kind) in planned {
+            if cancel.is_cancelled() {
+                return Ok(outcomes);
+            }
+            let result = verify_planned_file(file, group).and_then(|info| {
+                let action = Action::new(kind, plan.move_to.clone())?;
+                let entry = apply_action(&action, &info, &keeper)?;
+                journal.record(&entry)
+            });
+            outcomes.push(PlanOutcome {
+                path: file.path.clone(),
+                action: kind,
+                result,
+            });
+        }
+    }
+
+    Ok(outcomes)
+}
+
+/// Check that `file` still matches what the plan recorded for it
+pub fn verify_planned_file(file: &PlannedFile, group: &PlanGroup) -> anyhow::Result<FileInfo> {
+    let metadata = fs::symlink_metadata(&file.path)
+        .map_err(|_| anyhow::anyhow!("{} no longer exists", file.path.display()))?;
+    if !metadata.is_file() || metadata.len() != group.size {
+        anyhow::bail!("size of {} changed", file.path.display());
+    }
+    if modification_time(&file.path) != Some(file.mtime) {
+        anyhow::bail!("{} was modified after planning", file.path.display());
+    }
+
+    let mut info = FileInfo::new(file.path.clone(), group.size);
+    if info.calculate_hash_with(group.algorithm)?.to_hex() != group.hash {
+        anyhow::bail!("content of {} changed", file.path.display());
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::duplicate_group;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_plan_keeps_first_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let group = duplicate_group(temp_dir.path(), &["a", "b", "c"], b"planned");
+
+        let plan = Plan::from_groups(&[group], &Action::Delete);
+
+        let actions: Vec<_> = plan.groups[0].files.iter().map(|f| f.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                PlannedAction::Keep,
+                PlannedAction::Delete,
+                PlannedAction::Delete
+            ]
+        );
+        assert!(plan.groups[0].files.iter().all(|f| f.mtime > 0));
+
+        let plan = Plan::from_groups(&[], &Action::Move(PathBuf::from("trash")));
+        assert!(plan.move_to.unwrap().is_absolute());
+    }
+
+    #[test]
+    fn test_apply_skips_changed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let group = duplicate_group(temp_dir.path(), &["a", "b", "c"], b"planned");
+        let plan = Plan::from_groups(&[group], &Action::Delete);
+
+        // Same size, different content: only the hash re-check catches it
+        fs::write(temp_dir.path().join("c"), b"PLANNED").unwrap();
+
+        let mut journal = Journal::open(&temp_dir.path().join("journal.jsonl")).unwrap();
+        let outcomes = apply_plan(&plan, &mut journal, &CancellationToken::new()).unwrap();
+
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes[0].result.is_ok());
+        assert!(outcomes[1].result.is_err());
+        assert!(!temp_dir.path().join("b").exists());
+        assert!(temp_dir.path().join("c").exists());
+    }
+}
@@ -0,0 +1,234 @@
+//! File details for deciding which copy to keep
+//!
+//! Duplicates share their content but not their metadata, so the interactive
+//! mode shows modification time, owner, permissions and link count for every
+//! copy, plus a short preview of the shared content.
+
+use crate::modification_time;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Number of bytes read for a preview
+const PREVIEW_BYTES: usize = 4096;
+
+/// Metadata that can differ between copies with the same content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDetails {
+    /// Modification time in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    /// User name, or the numeric id if it has no name
+    pub owner: Option<String>,
+    /// `ls -l` style mode string, e.g. `-rw-r--r--`
+    pub permissions: String,
+    /// Number of hard links to the file
+    pub links: Option<u64>,
+}
+
+/// The start of a file's content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preview {
+    /// Leading lines of a text file
+    Text(Vec<String>),
+    /// Hex dump lines of a binary file
+    Binary(Vec<String>),
+}
+
+pub fn file_details(path: &Path) -> io::Result<FileDetails> {
+    let metadata = fs::symlink_metadata(path)?;
+    Ok(FileDetails {
+        modified: modification_time(path),
+        owner: owner(&metadata),
+        permissions: permissions(&metadata),
+        links: links(&metadata),
+    })
+}
+
+/// Preview at most `max_lines` lines of `path`
+///
+/// Control characters other than tabs are replaced with U+FFFD, so a file
+/// can't move the cursor or recolour the terminal it is previewed in.
+pub fn preview(path: &Path, max_lines: usize) -> io::Result<Preview> {
+    let mut buffer = Vec::with_capacity(PREVIEW_BYTES);
+    File::open(path)?
+        .take(PREVIEW_BYTES as u64)
+        .read_to_end(&mut buffer)?;
+
+    if is_text(&buffer) {
+        let text = String::from_utf8_lossy(&buffer);
+        Ok(Preview::Text(
+            text.lines().take(max_lines).map(sanitize).collect(),
+        ))
+    } else {
+        Ok(Preview::Binary(
+            hex_dump(&buffer).into_iter().take(max_lines).collect(),
+        ))
+    }
+}
+
+/// Whether `bytes` look like text: no NUL bytes and valid UTF-8, allowing
+/// for a multi-byte character cut off at the end of the buffer
+fn is_text(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && bytes.len() - e.valid_up_to() < 4,
+    }
+}
+
+/// `line` with every control character except tab replaced
+fn sanitize(line: &str) -> String {
+    line.chars()
+        .map(|c| {
+            if c.is_control() && c != '\t' {
+                char::REPLACEMENT_CHARACTER
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Format `bytes` as `hexdump -C` style lines of 16 bytes
+pub fn hex_dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut hex = String::new();
+            for (j, byte) in chunk.iter().enumerate() {
+                if j == 8 {
+                    hex.push(' ');
+                }
+                hex.push_str(&format!("{:02x} ", byte));
+            }
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {:<49} |{}|", i * 16, hex, ascii)
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn owner(metadata: &fs::Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let uid = metadata.uid();
+    Some(user_name(uid).unwrap_or_else(|| uid.to_string()))
+}
+
+#[cfg(not(unix))]
+fn owner(_metadata: &fs::Metadata) -> Option<String> {
+    None
+}
+
+/// Look up a user name in `/etc/passwd`
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    let passwd = fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let id = fields.nth(1)?;
+        (id.parse() == Ok(uid)).then(|| name.to_string())
+    })
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    mode_string(
+        metadata.file_type().is_symlink(),
+        metadata.permissions().mode(),
+    )
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &fs::Metadata) -> String {
+    if metadata.permissions().readonly() {
+        "read-only".to_string()
+    } else {
+        "read-write".to_string()
+    }
+}
+
+#[cfg(unix)]
+fn mode_string(symlink: bool, mode: u32) -> String {
+    let mut s = String::with_capacity(10);
+    s.push(if symlink { 'l' } else { '-' });
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+#[cfg(unix)]
+fn links(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.nlink())
+}
+
+#[cfg(not(unix))]
+fn links(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_preview_text_and_binary() {
+        let temp_dir = TempDir::new().unwrap();
+        let text = temp_dir.path().join("notes.txt");
+        fs::write(&text, "first\nsecond\nthird\n").unwrap();
+        let binary = temp_dir.path().join("blob.bin");
+        fs::write(&binary, [0u8, 1, 2, b'A']).unwrap();
+        let escapes = temp_dir.path().join("escapes.txt");
+        fs::write(&escapes, "\x1b[2Jred\rover\tab\n").unwrap();
+
+        assert_eq!(
+            preview(&text, 2).unwrap(),
+            Preview::Text(vec!["first".to_string(), "second".to_string()])
+        );
+        assert_eq!(
+            preview(&binary, 2).unwrap(),
+            Preview::Binary(vec![format!("00000000  {:<49} |...A|", "00 01 02 41 ")])
+        );
+        assert_eq!(
+            preview(&escapes, 2).unwrap(),
+            Preview::Text(vec!["\u{fffd}[2Jred\u{fffd}over\tab".to_string()])
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_details_reports_mode_and_links() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("file.txt");
+        fs::write(&path, "content").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        fs::hard_link(&path, temp_dir.path().join("link.txt")).unwrap();
+
+        let details = file_details(&path).unwrap();
+        assert_eq!(details.permissions, "-rw-r-----");
+        assert_eq!(details.links, Some(2));
+        assert!(details.owner.is_some());
+        assert!(details.modified.is_some());
+    }
+}
@@ -0,0 +1,40 @@
+//! Progress reporting for long scans
+//!
+//! Scanning functions that take a [`ProgressObserver`] report what they do as
+//! [`ProgressEvent`]s: every file found while walking, then the amount of data
+//! that needs hashing, then the bytes hashed as they are read. Events carry
+//! increments, not totals, so one observer can follow several calls. Any
+//! `FnMut(ProgressEvent)` closure is an observer.
+
+/// Something that happened during a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// A file of `size` bytes was found while walking
+    FileFound { size: u64 },
+    /// Hashing starts: `files` files totalling `bytes` bytes share a size
+    /// with another file and will be hashed
+    HashingStarted { files: u64, bytes: u64 },
+    /// Another `bytes` bytes were hashed
+    BytesHashed { bytes: u64 },
+    /// Hashing is complete
+    HashingFinished,
+}
+
+/// Receives [`ProgressEvent`]s
+pub trait ProgressObserver {
+    fn on_progress(&mut self, event: ProgressEvent);
+}
+
+impl<F: FnMut(ProgressEvent)> ProgressObserver for F {
+    fn on_progress(&mut self, event: ProgressEvent) {
+        self(event)
+    }
+}
+
+/// An observer that ignores every event
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn on_progress(&mut self, _event: ProgressEvent) {}
+}
@@ -0,0 +1,120 @@
+//! Progress bars for long scans, drawn on stderr
+//!
+//! A spinner counts files and bytes while the paths are walked, then a bar
+//! follows the bytes hashed with throughput and ETA. Nothing is drawn when
+//! stderr isn't a terminal.
+
+use file_dedup::progress::{ProgressEvent, ProgressObserver};
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::time::Duration;
+
+/// Files found between updates of the walk message
+const WALK_UPDATE_EVERY: u64 = 1024;
+
+pub struct ScanProgress {
+    walk: ProgressBar,
+    hash: Option<ProgressBar>,
+    files: u64,
+    bytes: u64,
+    to_hash: u64,
+    hashed: u64,
+    hidden: bool,
+}
+
+impl ScanProgress {
+    /// Progress bars on stderr, or none at all if `hidden`
+    pub fn new(hidden: bool) -> Self {
+        let walk = new_bar(None, hidden);
+        walk.set_style(
+            ProgressStyle::with_template("{spinner:.cyan} Walking: {pos} files, {msg}")
+                .expect("valid template"),
+        );
+        walk.set_message(HumanBytes(0).to_string());
+        walk.enable_steady_tick(Duration::from_millis(100));
+        Self {
+            walk,
+            hash: None,
+            files: 0,
+            bytes: 0,
+            to_hash: 0,
+            hashed: 0,
+            hidden,
+        }
+    }
+
+    /// What was done so far, for a scan that was stopped
+    pub fn summary(&self) -> String {
+        let found = format!("found {} files ({})", self.files, HumanBytes(self.bytes));
+        if self.to_hash == 0 {
+            found
+        } else {
+            format!(
+                "{}, hashed {} of {}",
+                found,
+                HumanBytes(self.hashed),
+                HumanBytes(self.to_hash)
+            )
+        }
+    }
+}
+
+impl ProgressObserver for ScanProgress {
+    fn on_progress(&mut self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::FileFound { size } => {
+                self.files += 1;
+                self.bytes += size;
+                if self.files.is_multiple_of(WALK_UPDATE_EVERY) {
+                    self.walk.set_position(self.files);
+                    self.walk.set_message(HumanBytes(self.bytes).to_string());
+                }
+            }
+            ProgressEvent::HashingStarted { bytes, .. } => {
+                self.walk.finish_and_clear();
+                self.to_hash += bytes;
+                let hash = new_bar(Some(bytes), self.hidden);
+                hash.set_style(
+                    ProgressStyle::with_template(
+                        "{spinner:.cyan} Hashing [{bar:30.cyan/blue}] {bytes}/{total_bytes} \
+                         ({binary_bytes_per_sec}, ETA {eta})",
+                    )
+                    .expect("valid template")
+                    .progress_chars("=> "),
+                );
+                hash.enable_steady_tick(Duration::from_millis(100));
+                self.hash = Some(hash);
+            }
+            ProgressEvent::BytesHashed { bytes } => {
+                self.hashed += bytes;
+                if let Some(hash) = &self.hash {
+                    hash.inc(bytes);
+                }
+            }
+            ProgressEvent::HashingFinished => {
+                if let Some(hash) = self.hash.take() {
+                    hash.finish_and_clear();
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ScanProgress {
+    fn drop(&mut self) {
+        // Don't leave a bar behind when a scan fails
+        self.walk.finish_and_clear();
+        if let Some(hash) = &self.hash {
+            hash.finish_and_clear();
+        }
+    }
+}
+
+fn new_bar(len: Option<u64>, hidden: bool) -> ProgressBar {
+    let target = if hidden {
+        ProgressDrawTarget::hidden()
+    } else {
+        // Hidden by indicatif itself when stderr isn't a terminal
+        ProgressDrawTarget::stderr()
+    };
+    ProgressBar::with_draw_target(len, target)
+}
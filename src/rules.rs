@@ -0,0 +1,113 @@
+//! Reusable decisions for interactive resolution
+//!
+//! A rule turns a choice made for one duplicate group into a decision that
+//! is applied automatically to later groups it matches.
+
+use crate::{DuplicateGroup, FileInfo};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A remembered decision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "rule", content = "dir")]
+pub enum Rule {
+    /// Keep every copy under this directory and resolve the others
+    KeepUnder(PathBuf),
+    /// Leave groups with any copy under this directory untouched
+    SkipUnder(PathBuf),
+}
+
+/// What a matching rule decided for a group
+#[derive(Debug)]
+pub enum RuleDecision<'a> {
+    Skip,
+    /// Apply the action to these files; `keeper` is one of the kept copies
+    Resolve {
+        keeper: &'a FileInfo,
+        files: Vec<&'a FileInfo>,
+    },
+}
+
+impl Rule {
+    /// The decision for `group`, or `None` if the rule doesn't match it
+    pub fn decide<'a>(&self, group: &'a DuplicateGroup) -> Option<RuleDecision<'a>> {
+        match self {
+            Rule::SkipUnder(dir) => group
+                .files
+                .iter()
+                .any(|f| f.path.starts_with(dir))
+                .then_some(RuleDecision::Skip),
+            Rule::KeepUnder(dir) => {
+                let (kept, files): (Vec<_>, Vec<_>) =
+                    group.files.iter().partition(|f| f.path.starts_with(dir));
+                let keeper = kept.first()?;
+                if files.is_empty() {
+                    return Some(RuleDecision::Skip);
+                }
+                Some(RuleDecision::Resolve { keeper, files })
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::KeepUnder(dir) => write!(f, "keep copies under {}", dir.display()),
+            Rule::SkipUnder(dir) => write!(f, "skip groups under {}", dir.display()),
+        }
+    }
+}
+
+/// The first rule matching `group`, with its index and decision
+pub fn first_match<'a>(
+    rules: &[Rule],
+    group: &'a DuplicateGroup,
+) -> Option<(usize, RuleDecision<'a>)> {
+    rules
+        .iter()
+        .enumerate()
+        .find_map(|(i, rule)| rule.decide(group).map(|decision| (i, decision)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{Digest, HashAlgorithm};
+
+    fn group(paths: &[&str]) -> DuplicateGroup {
+        let files = paths
+            .iter()
+            .map(|p| FileInfo::new(PathBuf::from(p), 10))
+            .collect();
+        DuplicateGroup::new(files, 10, Digest::new(HashAlgorithm::Xxh3, &[0; 8]))
+    }
+
+    #[test]
+    fn test_keep_under_resolves_other_copies() {
+        let group = group(&["/tmp/a.jpg", "/photos/originals/a.jpg", "/downloads/a.jpg"]);
+        let rule = Rule::KeepUnder(PathBuf::from("/photos/originals"));
+
+        let Some(RuleDecision::Resolve { keeper, files }) = rule.decide(&group) else {
+            panic!("rule should resolve the group");
+        };
+        assert_eq!(keeper.path, PathBuf::from("/photos/originals/a.jpg"));
+        assert_eq!(files.len(), 2);
+        assert!(rule.decide(&self::group(&["/x/a", "/y/a"])).is_none());
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules = vec![
+            Rule::SkipUnder(PathBuf::from("/tmp")),
+            Rule::KeepUnder(PathBuf::from("/photos")),
+        ];
+
+        let both = group(&["/tmp/a", "/photos/a"]);
+        let (index, decision) = first_match(&rules, &both).unwrap();
+        assert_eq!(index, 0);
+        assert!(matches!(decision, RuleDecision::Skip));
+        // Prefix matching is per path component, not per character
+        assert!(first_match(&rules, &group(&["/tmpfiles/a", "/other/a"])).is_none());
+    }
+}
@@ -0,0 +1,524 @@
+//! Configurable scans with structured results
+//!
+//! [`Scanner`] is the entry point for using file-dedup as a library. It is
+//! configured with a builder (roots, file filters, size bounds, symlink
+//! policy, hash algorithm, hashing threads, an optional memory limit and the
+//! [`FileSystem`] to read from) and
+//! returns a [`ScanResult`] holding the duplicate groups, statistics about
+//! the scan and every file that had to be skipped. Nothing is printed; follow
+//! a running scan with a [`ProgressObserver`].
+//!
+//! ```no_run
+//! use file_dedup::scanner::Scanner;
+//!
+//! let result = Scanner::new()
+//!     .root("photos")
+//!     .min_size(4096)
+//!     .filter(|path| path.extension().is_some_and(|ext| ext == "jpg"))
+//!     .threads(4)
+//!     .scan()?;
+//! for group in &result.groups {
+//!     println!("{} copies of {}", group.files.len(), group.files[0].path.display());
+//! }
+//! # Ok::<(), file_dedup::error::Error>(())
+//! ```
+
+use crate::bounded;
+use crate::cancel::CancellationToken;
+use crate::error::{Error, Result};
+use crate::hash::{Digest, HashAlgorithm};
+use crate::progress::{NoProgress, ProgressEvent, ProgressObserver};
+use crate::stream::Groups;
+use crate::vfs::{FileSystem, LocalFs};
+use crate::{sort_groups, visit_files, DuplicateGroup, FileInfo, SortOrder};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Bytes hashed by a worker before it reports progress
+const PROGRESS_CHUNK: u64 = 1024 * 1024;
+
+/// What to do with symbolic links found while walking
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Ignore them, so nothing outside the roots is scanned
+    #[default]
+    Skip,
+    /// Scan what they point to; a file reached through several links is
+    /// reported once per path
+    Follow,
+}
+
+/// Decides whether a file found while walking is scanned
+pub type FileFilter = Box<dyn Fn(&Path) -> bool + Send + Sync>;
+
+/// Numbers describing a finished scan
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    /// Files that passed the filters and size bounds
+    pub files_found: u64,
+    pub bytes_found: u64,
+    /// Files that shared their size with another file and were hashed
+    pub files_hashed: u64,
+    pub bytes_hashed: u64,
+    /// Files in duplicate groups, including the copy that would be kept
+    pub duplicate_files: u64,
+    /// Bytes freed by keeping one copy per group
+    pub wasted_bytes: u64,
+    /// Batches hashed one after another; more than one when the memory limit
+    /// made the scan spill its file list to disk
+    pub batches: usize,
+    pub elapsed: Duration,
+}
+
+/// Everything a scan found
+#[derive(Debug, Default)]
+pub struct ScanResult {
+    /// Duplicate groups, files sorted by path and groups by wasted space
+    pub groups: Vec<DuplicateGroup>,
+    pub stats: ScanStats,
+    /// Files and directories that were skipped, and why
+    pub warnings: Vec<Error>,
+}
+
+/// Builder for a duplicate scan
+pub struct Scanner {
+    roots: Vec<PathBuf>,
+    filters: Vec<FileFilter>,
+    min_size: u64,
+    max_size: u64,
+    symlinks: SymlinkPolicy,
+    algorithm: HashAlgorithm,
+    threads: usize,
+    memory_limit: Option<u64>,
+    cancel: CancellationToken,
+    fs: Arc<dyn FileSystem>,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            roots: Vec::new(),
+            filters: Vec::new(),
+            min_size: 1,
+            max_size: u64::MAX,
+            symlinks: SymlinkPolicy::default(),
+            algorithm: HashAlgorithm::default(),
+            threads: 1,
+            memory_limit: None,
+            cancel: CancellationToken::new(),
+            fs: Arc::new(LocalFs),
+        }
+    }
+}
+
+impl Scanner {
+    /// A scanner with no roots that skips empty files and symlinks, hashes
+    /// with XXH3 on one thread and keeps everything in memory
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan the file or directory `root`
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    /// Scan every file or directory in `roots`
+    pub fn roots<P: Into<PathBuf>>(mut self, roots: impl IntoIterator<Item = P>) -> Self {
+        self.roots.extend(roots.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only scan files for which `filter` returns true; with several filters
+    /// a file must pass all of them
+    pub fn filter(mut self, filter: impl Fn(&Path) -> bool + Send + Sync + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Skip files smaller than `bytes`; empty files are skipped unless this
+    /// is set to 0
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Skip files larger than `bytes`
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Compare file contents with `algorithm`
+    pub fn algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Hash on `threads` threads, each taking one size at a time; 0 counts
+    /// as 1
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Hold at most about `bytes` of file information at a time, walking the
+    /// roots twice and spilling the file list to temporary files if needed
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Stop the scan once `cancel` is cancelled, checking between files and
+    /// between read chunks
+    pub fn cancel_token(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Read the roots and files from `fs` instead of the local filesystem
+    pub fn filesystem(mut self, fs: impl FileSystem + 'static) -> Self {
+        self.fs = Arc::new(fs);
+        self
+    }
+
+    /// Run the scan
+    pub fn scan(&self) -> Result<ScanResult> {
+        self.scan_with_progress(&mut NoProgress)
+    }
+
+    /// Run the scan, reporting what it does to `progress`
+    ///
+    /// Fails only if the scan itself can't go on or was cancelled; files
+    /// that can't be read end up in [`ScanResult::warnings`].
+    pub fn scan_with_progress(&self, progress: &mut dyn ProgressObserver) -> Result<ScanResult> {
+        let mut groups = Vec::new();
+        let mut result = self.run(progress, &mut |group| groups.push(group))?;
+        sort_groups(&mut groups, SortOrder::Wasted);
+        result.groups = groups;
+        Ok(result)
+    }
+
+    /// Run the scan on a background thread, yielding every duplicate group
+    /// as soon as all files of its size are hashed
+    ///
+    /// See [`Groups`] for how the scan ends.
+    pub fn groups(self) -> Groups {
+        let cancel = self.cancel.clone();
+        Groups::spawn(self, cancel)
+    }
+
+    /// Run the scan, passing every duplicate group to `on_group` once all
+    /// files of its size are hashed
+    ///
+    /// The result holds the statistics and warnings but no groups.
+    pub(crate) fn run(
+        &self,
+        progress: &mut dyn ProgressObserver,
+        on_group: &mut dyn FnMut(DuplicateGroup),
+    ) -> Result<ScanResult> {
+        let started = Instant::now();
+        let mut result = ScanResult::default();
+        let mut duplicate_files = 0;
+        let mut wasted_bytes = 0;
+        let mut on_group = |group: DuplicateGroup| {
+            duplicate_files += group.files.len() as u64;
+            wasted_bytes += group.wasted();
+            on_group(group);
+        };
+
+        if let Some(memory_limit) = self.memory_limit {
+            bounded::scan(self, memory_limit, progress, &mut result, &mut on_group)?;
+        } else {
+            let mut files_by_size: HashMap<u64, Vec<FileInfo>> = HashMap::new();
+            self.walk(
+                |path, size| {
+                    progress.on_progress(ProgressEvent::FileFound { size });
+                    files_by_size
+                        .entry(size)
+                        .or_default()
+                        .push(FileInfo::new(path.to_path_buf(), size));
+                },
+                &mut result.warnings,
+            );
+
+            let stats = &mut result.stats;
+            for (size, files) in &files_by_size {
+                stats.files_found += files.len() as u64;
+                stats.bytes_found += size * files.len() as u64;
+                if files.len() > 1 {
+                    stats.files_hashed += files.len() as u64;
+                    stats.bytes_hashed += size * files.len() as u64;
+                }
+            }
+            stats.batches = 1;
+            progress.on_progress(ProgressEvent::HashingStarted {
+                files: stats.files_hashed,
+                bytes: stats.bytes_hashed,
+            });
+            self.hash_batch(files_by_size, progress, &mut result.warnings, &mut on_group);
+            progress.on_progress(ProgressEvent::HashingFinished);
+        }
+
+        if self.cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        result.stats.duplicate_files = duplicate_files;
+        result.stats.wasted_bytes = wasted_bytes;
+        result.stats.elapsed = started.elapsed();
+        Ok(result)
+    }
+
+    /// Call `on_file` with the path and size of every file the scan covers,
+    /// adding whatever can't be read to `warnings`
+    pub(crate) fn walk(&self, mut on_file: impl FnMut(&Path, u64), warnings: &mut Vec<Error>) {
+        for root in &self.roots {
+            visit_files(
+                self.fs.as_ref(),
+                root,
+                self.symlinks == SymlinkPolicy::Follow,
+                &self.cancel,
+                |path, size| {
+                    if (self.min_size..=self.max_size).contains(&size)
+                        && self.filters.iter().all(|filter| filter(path))
+                    {
+                        on_file(path, size);
+                    }
+                },
+                |e| warnings.push(e),
+            );
+        }
+    }
+
+    /// Hash every file of `files_by_size` that shares its size with another
+    /// one and pass each group of files with identical content to
+    /// `on_group`, one size after another in no particular order
+    ///
+    /// Progress, warnings and groups are passed back to the calling thread,
+    /// so `progress` and `on_group` needn't be thread-safe. Once the scan is
+    /// cancelled the threads stop after their current read chunk and the
+    /// groups are incomplete.
+    pub(crate) fn hash_batch(
+        &self,
+        files_by_size: HashMap<u64, Vec<FileInfo>>,
+        progress: &mut dyn ProgressObserver,
+        warnings: &mut Vec<Error>,
+        on_group: &mut dyn FnMut(DuplicateGroup),
+    ) {
+        let fs = self.fs.as_ref();
+        let (algorithm, cancel) = (self.algorithm, &self.cancel);
+        let queue = Mutex::new(
+            files_by_size
+                .into_iter()
+                .filter(|(_, files)| files.len() > 1) // No duplicates possible
+                .collect::<Vec<_>>()
+                .into_iter(),
+        );
+
+        std::thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            let workers: Vec<_> = (0..self.threads)
+                .map(|_| {
+                    let tx = tx.clone();
+                    let queue = &queue;
+                    scope.spawn(move || loop {
+                        let next = queue.lock().expect("hashing queue poisoned").next();
+                        let Some((size, files)) = next else {
+                            return;
+                        };
+                        let groups = hash_size(fs, size, files, algorithm, cancel, &tx);
+                        if !groups.is_empty() {
+                            let _ = tx.send(WorkerMessage::Found(groups));
+                        }
+                    })
+                })
+                .collect();
+            drop(tx);
+
+            for message in rx {
+                match message {
+                    WorkerMessage::Hashed(bytes) => {
+                        progress.on_progress(ProgressEvent::BytesHashed { bytes })
+                    }
+                    WorkerMessage::Skipped(warning) => warnings.push(warning),
+                    WorkerMessage::Found(groups) => groups.into_iter().for_each(&mut *on_group),
+                }
+            }
+            for worker in workers {
+                worker.join().expect("hashing thread panicked");
+            }
+        })
+    }
+}
+
+/// What a hashing thread tells the calling thread
+enum WorkerMessage {
+    Hashed(u64),
+    Skipped(Error),
+    /// The groups of one size, once all its files are hashed
+    Found(Vec<DuplicateGroup>),
+}
+
+/// Group the files of one size by content
+fn hash_size(
+    fs: &dyn FileSystem,
+    size: u64,
+    files: Vec<FileInfo>,
+    algorithm: HashAlgorithm,
+    cancel: &CancellationToken,
+    tx: &mpsc::Sender<WorkerMessage>,
+) -> Vec<DuplicateGroup> {
+    // Moving each file into its hash bucket
+    let mut files_by_hash: HashMap<Digest, Vec<FileInfo>> = HashMap::new();
+    let mut unreported = 0;
+
+    for mut file in files {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let mut hashed = 0;
+        let result = file.calculate_hash_observed(fs, algorithm, cancel, |bytes| {
+            hashed += bytes;
+            unreported += bytes;
+            if unreported >= PROGRESS_CHUNK {
+                let _ = tx.send(WorkerMessage::Hashed(unreported));
+                unreported = 0;
+            }
+        });
+        // Account for cached hashes, files that failed and files that
+        // changed size, so the total adds up
+        unreported += size.saturating_sub(hashed);
+
+        match result {
+            Ok(hash) => files_by_hash.entry(hash).or_default().push(file),
+            Err(Error::Cancelled) => break,
+            Err(e) => {
+                let _ = tx.send(WorkerMessage::Skipped(e));
+            }
+        }
+    }
+    if unreported > 0 {
+        let _ = tx.send(WorkerMessage::Hashed(unreported));
+    }
+
+    // Create duplicate groups for files with same hash
+    files_by_hash
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(hash, mut files)| {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            DuplicateGroup::new(files, size, hash)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::MemoryFs;
+
+    /// A scanner over an in-memory tree rooted at /tree
+    fn tree() -> Scanner {
+        let fs = MemoryFs::new()
+            .file("/tree/a.txt", "same content")
+            .file("/tree/sub/a.txt", "same content")
+            .file("/tree/b.log", "other content!")
+            .file("/tree/sub/b.log", "other content!")
+            .file("/tree/sub/c.log", "other content!")
+            .file("/tree/unique.txt", "only once")
+            .file("/tree/empty1", "")
+            .file("/tree/empty2", "")
+            .symlink("/tree/link.txt", "a.txt")
+            .symlink("/tree/sub/loop", "..");
+        Scanner::new().filesystem(fs)
+    }
+
+    #[test]
+    fn test_scan_result_and_stats() {
+        for threads in [1, 4] {
+            let result = tree().root("/tree").threads(threads).scan().unwrap();
+
+            assert_eq!(result.groups.len(), 2);
+            assert_eq!(result.groups[0].files.len(), 3);
+            assert!(result.warnings.is_empty());
+            let stats = &result.stats;
+            assert_eq!((stats.files_found, stats.bytes_found), (6, 75));
+            assert_eq!((stats.files_hashed, stats.bytes_hashed), (5, 66));
+            assert_eq!((stats.duplicate_files, stats.wasted_bytes), (5, 40));
+            assert_eq!(stats.batches, 1);
+        }
+    }
+
+    #[test]
+    fn test_filters_and_size_bounds() {
+        let scan = |scanner: Scanner| {
+            let result = scanner.root("/tree").scan().unwrap();
+            result.groups.iter().map(|g| g.size).collect::<Vec<_>>()
+        };
+
+        let logs = tree().filter(|p| p.extension().is_some_and(|e| e == "log"));
+        assert_eq!(scan(logs), vec![14]);
+        assert_eq!(scan(tree().max_size(13)), vec![12]);
+        assert_eq!(scan(tree().min_size(13)), vec![14]);
+        assert_eq!(scan(tree().min_size(0)), vec![14, 12, 0]);
+    }
+
+    #[test]
+    fn test_missing_root_is_a_warning() {
+        let missing = Path::new("/tree/missing");
+        let result = tree().root("/tree").root(missing).scan().unwrap();
+
+        assert_eq!(result.groups.len(), 2);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(matches!(&result.warnings[0], Error::RootNotFound(path) if *path == missing));
+    }
+
+    #[test]
+    fn test_followed_symlinks() {
+        let result = tree()
+            .root("/tree")
+            .symlinks(SymlinkPolicy::Follow)
+            .scan()
+            .unwrap();
+
+        assert_eq!(result.groups.len(), 2);
+        assert_eq!(result.stats.wasted_bytes, 52);
+        let linked: Vec<&Path> = result.groups[1]
+            .files
+            .iter()
+            .map(|f| f.path.as_path())
+            .collect();
+        assert_eq!(
+            linked,
+            ["/tree/a.txt", "/tree/link.txt", "/tree/sub/a.txt"].map(Path::new)
+        );
+        assert_eq!(result.warnings.len(), 1);
+        assert!(
+            matches!(&result.warnings[0], Error::SymlinkLoop(path) if path == Path::new("/tree/sub/loop"))
+        );
+    }
+
+    #[test]
+    fn test_cancelled_scan_stops() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        for memory_limit in [u64::MAX, 1] {
+            let result = tree()
+                .root("/tree")
+                .memory_limit(memory_limit)
+                .cancel_token(cancel.clone())
+                .scan();
+            assert!(matches!(result, Err(Error::Cancelled)));
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Standalone shell scripts of removal commands
+//!
+//! Instead of changing files in-process, duplicates can be written out as a
+//! POSIX `sh` script so the removal can be audited and run separately. Every
+//! line is a plain `rm`, `ln` or `mv` guarded by a re-check of the size of
+//! the duplicate and its keeper. Files that changed since the scan, and
+//! commands that fail, are counted and make the script exit with 1.
+
+use crate::actions::{move_destination, Action};
+use crate::DuplicateGroup;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const PRELUDE: &str = r#"set -u
+
+skipped=0
+
+same_size() {
+    [ -f "$1" ] && [ ! -L "$1" ] && [ "$(($(wc -c < "$1")))" -eq "$2" ]
+}
+
+# check DUPLICATE KEEPER SIZE
+check() {
+    if same_size "$1" "$3" && same_size "$2" "$3"; then
+        return 0
+    fi
+    printf 'changed since scan: %s\n' "$1" >&2
+    return 1
+}
+
+# absent DESTINATION: moves never replace a file
+absent() {
+    if [ -e "$1" ] || [ -L "$1" ]; then
+        printf 'destination exists: %s\n' "$1" >&2
+        return 1
+    fi
+}
+
+# skip DUPLICATE: count a file whose check or command failed
+skip() {
+    printf 'skipping: %s\n' "$1" >&2
+    skipped=$((skipped + 1))
+}
+"#;
+
+const EPILOGUE: &str = r#"
+if [ "$skipped" -gt 0 ]; then
+    printf '%s files skipped\n' "$skipped" >&2
+    exit 1
+fi
+"#;
+
+/// Write a script applying `action` to every file but the first of each group
+pub fn write_script(
+    groups: &[DuplicateGroup],
+    action: &Action,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(out, "#!/bin/sh")?;
+    writeln!(
+        out,
+        "# Duplicate removal script generated by file-dedup {}",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(
+        out,
+        "# Review the commands below, then run it with: sh <script>"
+    )?;
+    writeln!(out)?;
+    out.write_all(PRELUDE.as_bytes())?;
+
+    for (group_idx, group) in groups.iter().enumerate() {
+        let Some((keeper, duplicates)) = group.files.split_first() else {
+            continue;
+        };
+        let keeper_path = absolute(&keeper.path);
+
+        writeln!(out)?;
+        // Paths in comments use escaped form so a newline can't end the comment
+        writeln!(
+            out,
+            "# Group {}/{}: {} bytes, {} hash {}",
+            group_idx + 1,
+            groups.len(),
+            group.size,
+            group.hash.algorithm(),
+            group.hash
+        )?;
+        writeln!(out, "# keep {:?}", keeper_path)?;
+
+        for file in duplicates {
+            let path = shell_quote(&absolute(&file.path));
+            let keeper = shell_quote(&keeper_path);
+            let mut line = b"check ".to_vec();
+            line.extend_from_slice(&path);
+            line.push(b' ');
+            line.extend_from_slice(&keeper);
+            line.extend_from_slice(format!(" {} && ", group.size).as_bytes());
+            match action {
+                Action::Delete => {
+                    line.extend_from_slice(b"rm -f -- ");
+                    line.extend_from_slice(&path);
+                }
+                Action::Hardlink => {
+                    line.extend_from_slice(b"ln -f -- ");
+                    line.extend_from_slice(&keeper);
+                    line.push(b' ');
+                    line.extend_from_slice(&path);
+                }
+                Action::Symlink => {
+                    line.extend_from_slice(b"ln -sf -- ");
+                    line.extend_from_slice(&keeper);
+                    line.push(b' ');
+                    line.extend_from_slice(&path);
+                }
+                Action::Move(dir) => {
+                    let destination = move_destination(&absolute(dir), &absolute(&file.path));
+                    let destination_dir = destination.parent().unwrap_or(dir);
+                    let destination = shell_quote(&destination);
+                    line.extend_from_slice(b"absent ");
+                    line.extend_from_slice(&destination);
+                    line.extend_from_slice(b" && mkdir -p -- ");
+                    line.extend_from_slice(&shell_quote(destination_dir));
+                    line.extend_from_slice(b" && mv -- ");
+                    line.extend_from_slice(&path);
+                    line.push(b' ');
+                    line.extend_from_slice(&destination);
+                }
+            }
+            line.extend_from_slice(b" || skip ");
+            line.extend_from_slice(&path);
+            line.push(b'\n');
+            out.write_all(&line)?;
+        }
+    }
+
+    out.write_all(EPILOGUE.as_bytes())?;
+    Ok(())
+}
+
+/// Quote a path for POSIX `sh`
+///
+/// The path is wrapped in single quotes, inside which every byte (including
+/// newlines and invalid UTF-8) is literal; embedded single quotes become
+/// `'\''`.
+pub fn shell_quote(path: &Path) -> Vec<u8> {
+    let bytes = path_bytes(path);
+    let mut quoted = Vec::with_capacity(bytes.len() + 2);
+    quoted.push(b'\'');
+    for &byte in bytes.iter() {
+        if byte == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(byte);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    std::borrow::Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    std::borrow::Cow::Owned(path.to_string_lossy().into_owned().into_bytes())
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{Digest, HashAlgorithm};
+    use crate::FileInfo;
+
+    #[test]
+    fn test_shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote(Path::new("/a b/c")), b"'/a b/c'");
+        assert_eq!(shell_quote(Path::new("/it's")), b"'/it'\\''s'");
+        assert_eq!(shell_quote(Path::new("/line\nbreak")), b"'/line\nbreak'");
+    }
+
+    #[test]
+    fn test_script_lists_every_duplicate() {
+        let files = vec![
+            FileInfo::new(PathBuf::from("/data/keep.txt"), 10),
+            FileInfo::new(PathBuf::from("/data/dup1.txt"), 10),
+            FileInfo::new(PathBuf::from("/data/dup2.txt"), 10),
+        ];
+        let group = DuplicateGroup::new(files, 10, Digest::new(HashAlgorithm::Xxh3, &[0xab; 8]));
+
+        let mut script = Vec::new();
+        write_script(&[group], &Action::Delete, &mut script).unwrap();
+        let script = String::from_utf8(script).unwrap();
+
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains(
+            "check '/data/dup1.txt' '/data/keep.txt' 10 && rm -f -- '/data/dup1.txt' \
+             || skip '/data/dup1.txt'\n"
+        ));
+        assert!(script.contains("rm -f -- '/data/dup2.txt' || skip"));
+        assert!(!script.contains("rm -f -- '/data/keep.txt'"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_never_overwrites() {
+        use crate::test_util::duplicate_group;
+        use std::fs;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let group = duplicate_group(root, &["keep", "dup"], b"moved content");
+        let trash = root.join("trash");
+        let taken = move_destination(&absolute(&trash), &absolute(&root.join("dup")));
+        fs::create_dir_all(taken.parent().unwrap()).unwrap();
+        fs::write(&taken, b"already here!").unwrap();
+
+        let mut script = Vec::new();
+        write_script(&[group], &Action::Move(trash), &mut script).unwrap();
+        let script_path = root.join("dedup.sh");
+        fs::write(&script_path, script).unwrap();
+        let status = std::process::Command::new("sh")
+            .arg(&script_path)
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+
+        assert!(!status.success());
+        assert_eq!(fs::read(&taken).unwrap(), b"already here!");
+        assert!(root.join("dup").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_command_is_counted() {
+        use crate::test_util::duplicate_group;
+        use std::fs;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let group = duplicate_group(root, &["keep", "dup"], b"moved content");
+        // `mkdir -p` can't create directories below a regular file
+        let trash = root.join("trash");
+        fs::write(&trash, b"not a directory").unwrap();
+
+        let mut script = Vec::new();
+        write_script(&[group], &Action::Move(trash), &mut script).unwrap();
+        let script_path = root.join("dedup.sh");
+        fs::write(&script_path, script).unwrap();
+        let output = std::process::Command::new("sh")
+            .arg(&script_path)
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("1 files skipped"));
+        assert!(root.join("dup").exists());
+    }
+}
@@ -0,0 +1,321 @@
+//! Resumable interactive sessions
+//!
+//! A session stores the scan results together with the decision made for
+//! each duplicate group, and is saved after every group. Resuming continues
+//! with the first unresolved group without rescanning. Files that changed on
+//! disk since the session was saved are re-validated before being offered.
+
+use crate::actions::Action;
+use crate::hash::{Digest, HashAlgorithm};
+use crate::rules::Rule;
+use crate::{data_dir, modification_time, DuplicateGroup, FileInfo};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Current session file format version
+pub const SESSION_VERSION: u32 = 1;
+
+/// How far a group has been handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupStatus {
+    Pending,
+    Skipped,
+    Resolved,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionFile {
+    pub path: PathBuf,
+    /// Modification time in seconds since the Unix epoch when scanned
+    pub mtime: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionGroup {
+    pub size: u64,
+    pub hash: String,
+    /// Algorithm of `hash`
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_default")]
+    pub algorithm: HashAlgorithm,
+    pub files: Vec<SessionFile>,
+    pub status: GroupStatus,
+}
+
+/// Scan results and progress of an interactive run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    /// Paths that were scanned
+    pub paths: Vec<PathBuf>,
+    /// What is done with the duplicates; missing in sessions from older
+    /// versions
+    #[serde(default)]
+    pub action: Option<Action>,
+    pub groups: Vec<SessionGroup>,
+    /// Rules created so far, with the number of groups each was applied to
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub rule_hits: Vec<usize>,
+    /// Files resolved and bytes saved so far
+    #[serde(default)]
+    pub files_resolved: usize,
+    #[serde(default)]
+    pub bytes_saved: u64,
+}
+
+/// A group re-checked against the disk
+pub struct Revalidated {
+    /// The files that still match the recorded content
+    pub group: DuplicateGroup,
+    /// Files dropped because they vanished or their content changed
+    pub dropped: Vec<PathBuf>,
+}
+
+impl Session {
+    pub fn new(paths: &[PathBuf], groups: &[DuplicateGroup], action: &Action) -> Self {
+        let groups = groups
+            .iter()
+            .map(|group| SessionGroup {
+                size: group.size,
+                hash: group.hash.to_hex(),
+                algorithm: group.hash.algorithm(),
+                files: group
+                    .files
+                    .iter()
+                    .map(|file| SessionFile {
+                        // Absolute, so the session can be resumed from anywhere
+                        path: std::path::absolute(&file.path).unwrap_or_else(|_| file.path.clone()),
+                        mtime: modification_time(&file.path).unwrap_or(0),
+                    })
+                    .collect(),
+                status: GroupStatus::Pending,
+            })
+            .collect();
+
+        Self {
+            version: SESSION_VERSION,
+            paths: paths.to_vec(),
+            action: Some(action.clone()),
+            groups,
+            rules: Vec::new(),
+            rule_hits: Vec::new(),
+            files_resolved: 0,
+            bytes_saved: 0,
+        }
+    }
+
+    /// Default session location inside the user's data directory
+    pub fn default_path() -> PathBuf {
+        data_dir().join("session.json")
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let session: Session = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if session.version != SESSION_VERSION {
+            anyhow::bail!("Unsupported session version {}", session.version);
+        }
+        Ok(session)
+    }
+
+    /// Write the session, replacing the previous file atomically
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Refuse to continue with a different action than the session started
+    /// with, so a hardlink session never ends up deleting files
+    pub fn check_action(&self, action: &Action) -> anyhow::Result<()> {
+        match &self.action {
+            Some(saved) if saved != action => anyhow::bail!(
+                "Session was started with {}; resume it with the same --action",
+                describe(saved)
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Add the files of group `index` that were resolved to the totals, and
+    /// return the group's new status
+    ///
+    /// A group that was `stopped` part way through stays pending without the
+    /// files already resolved, so resuming offers only the rest.
+    pub fn record_resolved(
+        &mut self,
+        index: usize,
+        done: &[PathBuf],
+        stopped: bool,
+    ) -> GroupStatus {
+        let group = &mut self.groups[index];
+        self.files_resolved += done.len();
+        self.bytes_saved = self
+            .bytes_saved
+            .saturating_add(group.size.saturating_mul(done.len() as u64));
+        if stopped {
+            group.files.retain(|file| !done.contains(&file.path));
+            GroupStatus::Pending
+        } else if done.is_empty() {
+            GroupStatus::Skipped
+        } else {
+            GroupStatus::Resolved
+        }
+    }
+
+    /// Index of the first group without a decision
+    pub fn next_pending(&self) -> Option<usize> {
+        self.groups
+            .iter()
+            .position(|g| g.status == GroupStatus::Pending)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.groups
+            .iter()
+            .filter(|g| g.status == GroupStatus::Pending)
+            .count()
+    }
+
+    /// Rebuild group `index` from the disk
+    ///
+    /// Files whose size and modification time are unchanged are trusted.
+    /// Files that were touched are hashed again and dropped if their content
+    /// no longer matches; files that vanished are dropped as well.
+    pub fn revalidate(&self, index: usize) -> anyhow::Result<Revalidated> {
+        let recorded = &self.groups[index];
+        let hash = Digest::from_hex(recorded.algorithm, &recorded.hash).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Group {} has an invalid {} hash",
+                index + 1,
+                recorded.algorithm
+            )
+        })?;
+        let mut files = Vec::new();
+        let mut dropped = Vec::new();
+
+        for file in &recorded.files {
+            let size_matches = fs::symlink_metadata(&file.path)
+                .is_ok_and(|m| m.is_file() && m.len() == recorded.size);
+            let mut info = FileInfo::new(file.path.clone(), recorded.size);
+
+            let still_duplicate = size_matches
+                && if modification_time(&file.path) == Some(file.mtime) {
+                    info.hash = Some(hash);
+                    true
+                } else {
+                    info.calculate_hash_with(recorded.algorithm)
+                        .is_ok_and(|h| h == hash)
+                };
+
+            if still_duplicate {
+                files.push(info);
+            } else {
+                dropped.push(file.path.clone());
+            }
+        }
+
+        Ok(Revalidated {
+            group: DuplicateGroup::new(files, recorded.size, hash),
+            dropped,
+        })
+    }
+}
+
+/// `action` as it is given on the command line
+fn describe(action: &Action) -> String {
+    match action {
+        Action::Move(dir) => format!("--action move --move-to {}", dir.display()),
+        action => format!("--action {}", action.kind()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::duplicate_group;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_session_round_trip_and_progress() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = duplicate_group(temp_dir.path(), &["a", "b"], b"first");
+        let second = duplicate_group(temp_dir.path(), &["c", "d"], b"second");
+
+        let action = Action::Move(temp_dir.path().join("dups"));
+        let mut session = Session::new(&[temp_dir.path().to_path_buf()], &[first, second], &action);
+        session.groups[0].status = GroupStatus::Skipped;
+
+        let path = temp_dir.path().join("state/session.json");
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path).unwrap();
+
+        assert_eq!(loaded, session);
+        assert_eq!(loaded.next_pending(), Some(1));
+        assert_eq!(loaded.pending_count(), 1);
+        assert!(loaded.check_action(&action).is_ok());
+        let err = loaded.check_action(&Action::Delete).unwrap_err();
+        assert!(err.to_string().contains("--action move --move-to"));
+    }
+
+    #[test]
+    fn test_group_stopped_part_way_resumes_with_the_rest() {
+        let temp_dir = TempDir::new().unwrap();
+        let group = duplicate_group(temp_dir.path(), &["a", "b", "c"], b"content");
+        let mut session = Session::new(&[], &[group], &Action::Delete);
+        let path = temp_dir.path().join("session.json");
+
+        // Ctrl-C after "b" was deleted, before "c" was tried
+        let b = session.groups[0].files[1].path.clone();
+        fs::remove_file(&b).unwrap();
+        session.groups[0].status = session.record_resolved(0, &[b], true);
+        session.save(&path).unwrap();
+
+        let resumed = Session::load(&path).unwrap();
+        assert_eq!(resumed.next_pending(), Some(0));
+        assert_eq!((resumed.files_resolved, resumed.bytes_saved), (1, 7));
+        let revalidated = resumed.revalidate(0).unwrap();
+        assert!(revalidated.dropped.is_empty());
+        let left: Vec<_> = revalidated
+            .group
+            .files
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        assert_eq!(left, [temp_dir.path().join("a"), temp_dir.path().join("c")]);
+
+        let mut finished = resumed;
+        let c = temp_dir.path().join("c");
+        assert_eq!(
+            finished.record_resolved(0, &[c], false),
+            GroupStatus::Resolved
+        );
+        assert_eq!(finished.files_resolved, 2);
+    }
+
+    #[test]
+    fn test_revalidate_drops_changed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let group = duplicate_group(temp_dir.path(), &["a", "b", "c"], b"content");
+        let mut session = Session::new(&[], &[group], &Action::Delete);
+        // Pretend the scan happened long ago so the rewrite below is noticed
+        session.groups[0].files[1].mtime = 0;
+
+        fs::write(temp_dir.path().join("b"), b"CONTENT").unwrap();
+        fs::remove_file(temp_dir.path().join("c")).unwrap();
+
+        let revalidated = session.revalidate(0).unwrap();
+        assert_eq!(
+            revalidated.dropped,
+            vec![temp_dir.path().join("b"), temp_dir.path().join("c")]
+        );
+        assert_eq!(revalidated.group.files.len(), 1);
+        assert_eq!(revalidated.group.files[0].path, temp_dir.path().join("a"));
+    }
+}
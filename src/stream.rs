@@ -0,0 +1,238 @@
+//! Duplicate groups delivered while a scan is still running
+//!
+//! [`Scanner::groups`] runs the scan on a background thread and returns
+//! [`Groups`], which yields each duplicate group as soon as every file of its
+//! size has been hashed. Groups come in no particular order. With the
+//! `stream` feature, [`Groups`] is also a `futures_core::Stream` for async
+//! consumers.
+//!
+//! ```no_run
+//! use file_dedup::scanner::Scanner;
+//!
+//! let mut groups = Scanner::new().root("photos").threads(4).groups();
+//! for group in &mut groups {
+//!     println!("{} copies of {}", group.files.len(), group.files[0].path.display());
+//! }
+//! let result = groups.finish()?;
+//! println!("{} files skipped", result.warnings.len());
+//! # Ok::<(), file_dedup::error::Error>(())
+//! ```
+
+use crate::cancel::CancellationToken;
+use crate::error::Result;
+use crate::progress::NoProgress;
+use crate::scanner::{ScanResult, Scanner};
+use crate::DuplicateGroup;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::Waker;
+use std::thread::JoinHandle;
+
+/// Groups found by the scan and not taken yet
+#[derive(Default)]
+struct Queue {
+    groups: VecDeque<DuplicateGroup>,
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// The queue shared by the scanning thread and [`Groups`]
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().expect("group queue poisoned")
+    }
+
+    fn push(&self, group: DuplicateGroup) {
+        let mut queue = self.lock();
+        queue.groups.push_back(group);
+        self.notify(queue);
+    }
+
+    fn notify(&self, mut queue: MutexGuard<'_, Queue>) {
+        let waker = queue.waker.take();
+        drop(queue);
+        self.ready.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Marks the queue done when the scanning thread ends, even by panicking
+struct Done(Arc<Shared>);
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        let mut queue = self.0.lock();
+        queue.done = true;
+        self.0.notify(queue);
+    }
+}
+
+/// Duplicate groups of a scan running in the background
+///
+/// Iterating blocks until the next group is found and ends with the scan.
+/// A scan that fails or is cancelled just ends early; [`Groups::finish`]
+/// tells why. Dropping `Groups` before the scan is over cancels the scan's
+/// [`CancellationToken`].
+pub struct Groups {
+    shared: Arc<Shared>,
+    scan: Option<JoinHandle<Result<ScanResult>>>,
+    cancel: CancellationToken,
+}
+
+impl Groups {
+    pub(crate) fn spawn(scanner: Scanner, cancel: CancellationToken) -> Self {
+        let shared = Arc::new(Shared::default());
+        let done = Done(Arc::clone(&shared));
+        let scan = std::thread::spawn(move || {
+            let done = done;
+            scanner.run(&mut NoProgress, &mut |group| done.0.push(group))
+        });
+        Self {
+            shared,
+            scan: Some(scan),
+            cancel,
+        }
+    }
+
+    /// Wait for the scan to end and return its statistics and warnings
+    ///
+    /// Groups not taken from the iterator yet are in [`ScanResult::groups`],
+    /// in the order they were found.
+    pub fn finish(mut self) -> Result<ScanResult> {
+        let scan = self.scan.take().expect("scan is only joined once");
+        let mut result = scan.join().expect("scanning thread panicked")?;
+        result.groups = self.shared.lock().groups.drain(..).collect();
+        Ok(result)
+    }
+}
+
+impl Iterator for Groups {
+    type Item = DuplicateGroup;
+
+    fn next(&mut self) -> Option<DuplicateGroup> {
+        let mut queue = self.shared.lock();
+        loop {
+            if let Some(group) = queue.groups.pop_front() {
+                return Some(group);
+            }
+            if queue.done {
+                return None;
+            }
+            queue = self.shared.ready.wait(queue).expect("group queue poisoned");
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl futures_core::Stream for Groups {
+    type Item = DuplicateGroup;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<DuplicateGroup>> {
+        use std::task::Poll;
+
+        let mut queue = self.shared.lock();
+        if let Some(group) = queue.groups.pop_front() {
+            Poll::Ready(Some(group))
+        } else if queue.done {
+            Poll::Ready(None)
+        } else {
+            queue.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Groups {
+    fn drop(&mut self) {
+        // Nobody is left to take the groups
+        if self.scan.as_ref().is_some_and(|scan| !scan.is_finished()) {
+            self.cancel.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scanner::Scanner;
+    use crate::DuplicateGroup;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_groups_match_scan() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for (name, content) in [
+            ("a1", "first"),
+            ("a2", "first"),
+            ("b1", "second!"),
+            ("b2", "second!"),
+            ("c1", "third, 3 copies"),
+            ("c2", "third, 3 copies"),
+            ("c3", "third, 3 copies"),
+            ("unique", "the only file of this size"),
+        ] {
+            fs::write(root.join(name), content).unwrap();
+        }
+        let expected = Scanner::new().root(root).scan().unwrap();
+
+        let paths = |groups: &[DuplicateGroup]| -> Vec<Vec<PathBuf>> {
+            let mut paths: Vec<Vec<PathBuf>> = groups
+                .iter()
+                .map(|g| g.files.iter().map(|f| f.path.clone()).collect())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        for threads in [1, 4] {
+            let mut groups = Scanner::new().root(root).threads(threads).groups();
+            // Take one group, and leave the rest to finish()
+            let mut found = vec![groups.next().unwrap()];
+            let result = groups.finish().unwrap();
+            found.extend(result.groups);
+
+            assert_eq!(paths(&found), paths(&expected.groups));
+            assert_eq!(result.stats.duplicate_files, 7);
+            assert_eq!(result.stats.wasted_bytes, expected.stats.wasted_bytes);
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_groups_stream() {
+        use futures_core::Stream;
+        use std::pin::Pin;
+        use std::task::{Context, Poll, Waker};
+
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["a", "b"] {
+            fs::write(temp_dir.path().join(name), "same").unwrap();
+        }
+
+        let mut groups = Scanner::new().root(temp_dir.path()).groups();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut found = Vec::new();
+        loop {
+            match Pin::new(&mut groups).poll_next(&mut cx) {
+                Poll::Ready(Some(group)) => found.push(group),
+                Poll::Ready(None) => break,
+                Poll::Pending => std::thread::yield_now(),
+            }
+        }
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].files.len(), 2);
+    }
+}
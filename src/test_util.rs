@@ -0,0 +1,26 @@
+//! Fixtures shared by the unit tests
+
+use crate::{DuplicateGroup, FileInfo};
+use std::fs;
+use std::path::Path;
+
+/// Write `content` to `dir/name`, creating the directories on the way, and
+/// return it hashed
+pub(crate) fn write_file(dir: &Path, name: &str, content: &[u8]) -> FileInfo {
+    let path = dir.join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, content).unwrap();
+    let mut info = FileInfo::new(path, content.len() as u64);
+    info.calculate_hash().unwrap();
+    info
+}
+
+/// Write `content` to every one of `names` in `dir` and group the copies
+pub(crate) fn duplicate_group(dir: &Path, names: &[&str], content: &[u8]) -> DuplicateGroup {
+    let files: Vec<FileInfo> = names
+        .iter()
+        .map(|name| write_file(dir, name, content))
+        .collect();
+    let hash = files[0].hash.unwrap();
+    DuplicateGroup::new(files, content.len() as u64, hash)
+}
@@ -0,0 +1,528 @@
+//! Full-screen terminal browser for duplicate groups
+//!
+//! Groups are listed in `--sort` order next to the files of the selected group
+//! and their metadata. Files are marked with the keyboard, decisions are
+//! queued per group and reviewed together before anything changes on disk.
+
+use crate::{format_file_size, format_timestamp};
+use file_dedup::actions::Action;
+use file_dedup::error::Error;
+use file_dedup::{modification_time, DuplicateGroup};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeMap;
+
+/// A queued decision: apply the action to `files` of `group`, keep the rest
+pub struct Decision {
+    pub group: usize,
+    pub files: Vec<usize>,
+}
+
+/// Browse `groups` until the user commits or quits
+///
+/// Returns the queued decisions on commit, or `None` if the user quit.
+/// Ctrl-C, which arrives as a key press in raw mode, discards the decisions
+/// and fails with [`Error::Cancelled`].
+pub fn run(groups: &[DuplicateGroup], action: &Action) -> anyhow::Result<Option<Vec<Decision>>> {
+    let mut terminal = ratatui::init();
+    let result = App::new(groups, action).run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Groups,
+    Files,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Browse,
+    Filter,
+    Review,
+}
+
+struct App<'a> {
+    groups: &'a [DuplicateGroup],
+    action: &'a Action,
+    /// Group indices in display order
+    order: Vec<usize>,
+    /// Groups of `order` that match `filter`
+    visible: Vec<usize>,
+    filter: String,
+    group_state: ListState,
+    file_state: ListState,
+    /// Cursor in the review list, one line per file plus a blank line per
+    /// group
+    review_state: ListState,
+    /// Files marked for the action, per group
+    marks: Vec<Vec<bool>>,
+    queued: BTreeMap<usize, Vec<usize>>,
+    focus: Focus,
+    mode: Mode,
+    message: Option<String>,
+    confirm_quit: bool,
+}
+
+impl<'a> App<'a> {
+    fn new(groups: &'a [DuplicateGroup], action: &'a Action) -> Self {
+        let order: Vec<usize> = (0..groups.len()).collect();
+
+        let mut app = Self {
+            groups,
+            action,
+            visible: order.clone(),
+            order,
+            filter: String::new(),
+            group_state: ListState::default(),
+            file_state: ListState::default(),
+            review_state: ListState::default(),
+            marks: groups.iter().map(|g| vec![false; g.files.len()]).collect(),
+            queued: BTreeMap::new(),
+            focus: Focus::Groups,
+            mode: Mode::Browse,
+            message: None,
+            confirm_quit: false,
+        };
+        app.select_group(0);
+        app
+    }
+
+    fn run(mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<Option<Vec<Decision>>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Err(Error::Cancelled.into());
+            }
+
+            match self.mode {
+                Mode::Filter => self.handle_filter_key(key.code),
+                Mode::Review => match key.code {
+                    KeyCode::Enter | KeyCode::Char('c') => {
+                        let decisions = std::mem::take(&mut self.queued)
+                            .into_iter()
+                            .map(|(group, files)| Decision { group, files })
+                            .collect();
+                        return Ok(Some(decisions));
+                    }
+                    KeyCode::Esc | KeyCode::Char('r') | KeyCode::Char('q') => {
+                        self.mode = Mode::Browse;
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => self.scroll_review(-1),
+                    KeyCode::Down | KeyCode::Char('j') => self.scroll_review(1),
+                    KeyCode::PageUp => self.scroll_review(-10),
+                    KeyCode::PageDown => self.scroll_review(10),
+                    _ => {}
+                },
+                Mode::Browse => {
+                    if key.code == KeyCode::Char('q') {
+                        if self.queued.is_empty() || self.confirm_quit {
+                            return Ok(None);
+                        }
+                        self.confirm_quit = true;
+                        self.message = Some(format!(
+                            "{} queued decisions will be discarded, press q again to quit",
+                            self.queued.len()
+                        ));
+                        continue;
+                    }
+                    self.confirm_quit = false;
+                    self.message = None;
+                    self.handle_browse_key(key.code);
+                }
+            }
+        }
+    }
+
+    fn handle_filter_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Enter => self.mode = Mode::Browse,
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.apply_filter();
+                self.mode = Mode::Browse;
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.apply_filter();
+            }
+            KeyCode::Char(c) => {
+                self.filter.push(c);
+                self.apply_filter();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_browse_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char('/') => {
+                self.mode = Mode::Filter;
+                self.focus = Focus::Groups;
+            }
+            KeyCode::Char('r') => {
+                self.mode = Mode::Review;
+                self.review_state = ListState::default().with_selected(Some(0));
+            }
+            _ => match self.focus {
+                Focus::Groups => match code {
+                    KeyCode::Up | KeyCode::Char('k') => self.move_group(-1),
+                    KeyCode::Down | KeyCode::Char('j') => self.move_group(1),
+                    KeyCode::PageUp => self.move_group(-10),
+                    KeyCode::PageDown => self.move_group(10),
+                    KeyCode::Enter | KeyCode::Right | KeyCode::Tab | KeyCode::Char('l')
+                        if self.current_group().is_some() =>
+                    {
+                        self.focus = Focus::Files;
+                    }
+                    _ => {}
+                },
+                Focus::Files => self.handle_files_key(code),
+            },
+        }
+    }
+
+    fn handle_files_key(&mut self, code: KeyCode) {
+        let Some(group_idx) = self.current_group() else {
+            return;
+        };
+        let file_count = self.groups[group_idx].files.len();
+        let cursor = self.file_state.selected().unwrap_or(0);
+
+        match code {
+            KeyCode::Esc | KeyCode::Left | KeyCode::Tab | KeyCode::Char('h') => {
+                self.focus = Focus::Groups;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.file_state.select(Some(cursor.saturating_sub(1)));
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.file_state
+                    .select(Some((cursor + 1).min(file_count - 1)));
+            }
+            KeyCode::Char(' ') | KeyCode::Char('x') => {
+                self.marks[group_idx][cursor] ^= true;
+            }
+            KeyCode::Char('n') => {
+                // Mark everything except the most recently modified copy
+                let newest = self.groups[group_idx]
+                    .files
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(_, f)| modification_time(&f.path))
+                    .map(|(i, _)| i)
+                    .unwrap_or(0);
+                self.marks[group_idx] = (0..file_count).map(|i| i != newest).collect();
+            }
+            KeyCode::Char('d') => {
+                // Mark every copy in the same directory tree as the cursor
+                let group = &self.groups[group_idx];
+                if let Some(dir) = group.files[cursor].path.parent() {
+                    for (mark, file) in self.marks[group_idx].iter_mut().zip(&group.files) {
+                        if file.path.starts_with(dir) {
+                            *mark = true;
+                        }
+                    }
+                }
+            }
+            KeyCode::Char('c') => {
+                self.marks[group_idx] = vec![false; file_count];
+            }
+            KeyCode::Enter => self.queue_current(group_idx),
+            KeyCode::Char('u') if self.queued.remove(&group_idx).is_some() => {
+                self.message = Some("Decision removed from queue".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn queue_current(&mut self, group_idx: usize) {
+        let marked: Vec<usize> = self.marks[group_idx]
+            .iter()
+            .enumerate()
+            .filter(|(_, &m)| m)
+            .map(|(i, _)| i)
+            .collect();
+
+        if marked.len() == self.marks[group_idx].len() {
+            self.message = Some("You must keep at least one file in each group".to_string());
+        } else if marked.is_empty() {
+            self.queued.remove(&group_idx);
+            self.message = Some("Nothing marked, group left unchanged".to_string());
+        } else {
+            self.message = Some(format!(
+                "Queued: {} {} file(s)",
+                self.action.kind(),
+                marked.len()
+            ));
+            self.queued.insert(group_idx, marked);
+            self.focus = Focus::Groups;
+            self.move_group(1);
+        }
+    }
+
+    fn apply_filter(&mut self) {
+        let needle = self.filter.to_lowercase();
+        self.visible = self
+            .order
+            .iter()
+            .copied()
+            .filter(|&i| {
+                needle.is_empty()
+                    || self.groups[i]
+                        .files
+                        .iter()
+                        .any(|f| f.path.to_string_lossy().to_lowercase().contains(&needle))
+            })
+            .collect();
+        self.select_group(0);
+    }
+
+    fn current_group(&self) -> Option<usize> {
+        self.group_state
+            .selected()
+            .and_then(|i| self.visible.get(i).copied())
+    }
+
+    fn select_group(&mut self, position: usize) {
+        if self.visible.is_empty() {
+            self.group_state.select(None);
+            self.file_state.select(None);
+        } else {
+            self.group_state
+                .select(Some(position.min(self.visible.len() - 1)));
+            self.file_state.select(Some(0));
+        }
+    }
+
+    fn scroll_review(&mut self, delta: isize) {
+        let lines: usize = self
+            .queued
+            .keys()
+            .map(|&i| self.groups[i].files.len() + 1)
+            .sum();
+        let current = self.review_state.selected().unwrap_or(0);
+        let next = current.saturating_add_signed(delta);
+        self.review_state
+            .select(Some(next.min(lines.saturating_sub(1))));
+    }
+
+    fn move_group(&mut self, delta: isize) {
+        let current = self.group_state.selected().unwrap_or(0);
+        self.select_group(current.saturating_add_signed(delta));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+        if self.mode == Mode::Review {
+            self.draw_review(frame, main);
+        } else {
+            let [left, right] =
+                Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                    .areas(main);
+            let [files, details] =
+                Layout::vertical([Constraint::Min(5), Constraint::Length(8)]).areas(right);
+            self.draw_groups(frame, left);
+            self.draw_files(frame, files);
+            self.draw_details(frame, details);
+        }
+
+        let help = match (self.mode, self.focus) {
+            (Mode::Filter, _) => format!("Filter: {}▏ (enter apply, esc clear)", self.filter),
+            (Mode::Review, _) => "↑↓ scroll  enter/c commit  esc back".to_string(),
+            (Mode::Browse, Focus::Groups) => {
+                "↑↓ move  enter open  / filter  r review  q quit".to_string()
+            }
+            (Mode::Browse, Focus::Files) => {
+                "space mark  n all but newest  d mark dir  c clear  enter queue  u unqueue  esc back"
+                    .to_string()
+            }
+        };
+        let status_line = match &self.message {
+            Some(message) => Line::from(vec![
+                Span::styled(message.clone(), Style::default().fg(Color::Yellow)),
+                Span::raw("  │  "),
+                Span::raw(help),
+            ]),
+            None => Line::from(help),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+
+    fn draw_groups(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let group = &self.groups[i];
+                let marker = if self.queued.contains_key(&i) {
+                    Span::styled("✔ ", Style::default().fg(Color::Green))
+                } else {
+                    Span::raw("  ")
+                };
+                let first = group
+                    .files
+                    .first()
+                    .map(|f| f.path.display().to_string())
+                    .unwrap_or_default();
+                ListItem::new(Line::from(vec![
+                    marker,
+                    Span::styled(
+                        format!("{:>9}", format_file_size(group.wasted())),
+                        Style::default().fg(Color::Magenta),
+                    ),
+                    Span::raw(format!("  {}× ", group.files.len())),
+                    Span::styled(first, Style::default().add_modifier(Modifier::DIM)),
+                ]))
+            })
+            .collect();
+
+        let title = if self.filter.is_empty() {
+            format!(" Groups ({}) ", self.visible.len())
+        } else {
+            format!(
+                " Groups ({}/{}) matching \"{}\" ",
+                self.visible.len(),
+                self.order.len(),
+                self.filter
+            )
+        };
+        let list = List::new(items)
+            .block(self.block(title, self.focus == Focus::Groups))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.group_state);
+    }
+
+    fn draw_files(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(group_idx) = self.current_group() else {
+            frame.render_widget(self.block(" Files ".to_string(), false), area);
+            return;
+        };
+        let group = &self.groups[group_idx];
+
+        let items: Vec<ListItem> = group
+            .files
+            .iter()
+            .zip(&self.marks[group_idx])
+            .map(|(file, &marked)| {
+                let (checkbox, style) = if marked {
+                    (
+                        format!("[{}] ", self.action.kind()),
+                        Style::default().fg(Color::Red),
+                    )
+                } else {
+                    ("[keep] ".to_string(), Style::default().fg(Color::Green))
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(checkbox, style),
+                    Span::raw(file.path.display().to_string()),
+                ]))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(self.block(
+                format!(" Files ({}) ", group.files.len()),
+                self.focus == Focus::Files,
+            ))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.file_state);
+    }
+
+    fn draw_details(&self, frame: &mut Frame, area: Rect) {
+        let mut lines = Vec::new();
+        if let Some(group_idx) = self.current_group() {
+            let group = &self.groups[group_idx];
+            let cursor = self.file_state.selected().unwrap_or(0);
+            if let Some(file) = group.files.get(cursor) {
+                lines.push(Line::from(format!("Path:     {}", file.path.display())));
+                lines.push(Line::from(format!(
+                    "Size:     {}",
+                    format_file_size(file.size)
+                )));
+                lines.push(Line::from(format!(
+                    "Modified: {}",
+                    modification_time(&file.path)
+                        .map(format_timestamp)
+                        .unwrap_or_else(|| "unknown".to_string())
+                )));
+            }
+            lines.push(Line::from(format!("Hash:     {}", group.hash)));
+            lines.push(Line::from(format!(
+                "Copies:   {}, wasting {}",
+                group.files.len(),
+                format_file_size(group.wasted())
+            )));
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(self.block(" Details ".to_string(), false)),
+            area,
+        );
+    }
+
+    fn draw_review(&mut self, frame: &mut Frame, area: Rect) {
+        let mut items = Vec::new();
+        let mut total = 0u64;
+        for (&group_idx, files) in &self.queued {
+            let group = &self.groups[group_idx];
+            total = total.saturating_add(group.size.saturating_mul(files.len() as u64));
+            for (i, file) in group.files.iter().enumerate() {
+                let line = if files.contains(&i) {
+                    Line::from(vec![
+                        Span::styled(
+                            format!("{:>9} ", self.action.kind()),
+                            Style::default().fg(Color::Red),
+                        ),
+                        Span::raw(file.path.display().to_string()),
+                    ])
+                } else {
+                    Line::from(vec![
+                        Span::styled("     keep ", Style::default().fg(Color::Green)),
+                        Span::styled(
+                            file.path.display().to_string(),
+                            Style::default().add_modifier(Modifier::DIM),
+                        ),
+                    ])
+                };
+                items.push(ListItem::new(line));
+            }
+            items.push(ListItem::new(""));
+        }
+
+        let title = format!(
+            " Review: {} groups queued, {} to free ",
+            self.queued.len(),
+            format_file_size(total)
+        );
+        let list = List::new(items)
+            .block(self.block(title, true))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.review_state);
+    }
+
+    fn block(&self, title: String, focused: bool) -> Block<'static> {
+        let style = if focused {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default()
+        };
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(style)
+            .title(title)
+    }
+}
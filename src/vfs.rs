@@ -0,0 +1,303 @@
+//! The filesystem a scan reads from
+//!
+//! Scanning and hashing only touch files through [`FileSystem`], so a
+//! [`crate::scanner::Scanner`] can be pointed at anything that lists
+//! directories and opens files: [`LocalFs`] for the local disk (the
+//! default), [`MemoryFs`] for a tree built in memory, or an implementation
+//! of your own for archive contents or remote storage. The directory
+//! reports read through it too: pass the same filesystem to
+//! [`crate::dirs::find_duplicate_dirs_in`] and
+//! [`crate::overlap::find_overlaps_in`].
+//!
+//! ```
+//! use file_dedup::scanner::Scanner;
+//! use file_dedup::vfs::MemoryFs;
+//!
+//! let fs = MemoryFs::new()
+//!     .file("/photos/a.jpg", "same")
+//!     .file("/photos/copy/a.jpg", "same")
+//!     .file("/photos/b.jpg", "different");
+//! let result = Scanner::new().filesystem(fs).root("/photos").scan()?;
+//! assert_eq!(result.groups.len(), 1);
+//! # Ok::<(), file_dedup::error::Error>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Symlinks a path may pass through before [`MemoryFs`] gives up
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// What kind of entry a path is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    /// Sockets, devices and the like, which are never scanned
+    Other,
+}
+
+/// The part of a file's metadata a scan needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub file_type: FileType,
+    /// Size in bytes; only meaningful for files
+    pub len: u64,
+}
+
+/// A tree of directories and files that can be scanned
+///
+/// Errors are plain [`io::Error`]s; use [`io::ErrorKind::NotFound`] and
+/// [`io::ErrorKind::PermissionDenied`] where they apply, so they are reported
+/// as [`crate::error::Error::Vanished`] and
+/// [`crate::error::Error::PermissionDenied`].
+pub trait FileSystem: Send + Sync {
+    /// Metadata of `path`, following symlinks
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Metadata of `path` itself, even if it is a symlink
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Paths of the entries in the directory `path`, in any order
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Open the file at `path` for reading
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>>;
+
+    /// `path` with every symlink resolved, used to detect loops when
+    /// following symlinks
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+}
+
+/// The local filesystem, through [`std::fs`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalFs;
+
+impl FileSystem for LocalFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::metadata(path).map(Metadata::from)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::symlink_metadata(path).map(Metadata::from)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+impl From<fs::Metadata> for Metadata {
+    fn from(metadata: fs::Metadata) -> Self {
+        let file_type = metadata.file_type();
+        let file_type = if file_type.is_symlink() {
+            FileType::Symlink
+        } else if file_type.is_dir() {
+            FileType::Dir
+        } else if file_type.is_file() {
+            FileType::File
+        } else {
+            FileType::Other
+        };
+        Self {
+            file_type,
+            len: metadata.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    File(Arc<[u8]>),
+    Dir,
+    Symlink(PathBuf),
+}
+
+/// A tree held in memory, for tests and synthetic scans
+///
+/// Adding an entry creates its parent directories. Relative symlink targets
+/// are resolved from the directory holding the link.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFs {
+    nodes: BTreeMap<PathBuf, Node>,
+}
+
+impl MemoryFs {
+    /// An empty tree
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file at `path` holding `contents`
+    pub fn file(self, path: impl Into<PathBuf>, contents: impl AsRef<[u8]>) -> Self {
+        self.insert(path.into(), Node::File(contents.as_ref().into()))
+    }
+
+    /// Add an empty directory at `path`
+    pub fn dir(self, path: impl Into<PathBuf>) -> Self {
+        self.insert(path.into(), Node::Dir)
+    }
+
+    /// Add a symlink at `path` pointing to `target`
+    pub fn symlink(self, path: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+        self.insert(path.into(), Node::Symlink(target.into()))
+    }
+
+    fn insert(mut self, path: PathBuf, node: Node) -> Self {
+        for parent in path.ancestors().skip(1) {
+            if !parent.as_os_str().is_empty() {
+                self.nodes.entry(parent.to_path_buf()).or_insert(Node::Dir);
+            }
+        }
+        self.nodes.insert(path, node);
+        self
+    }
+
+    /// `path` with the symlinks along it resolved, and the last one too if
+    /// `follow` is set
+    fn resolve(&self, path: &Path, follow: bool, hops: &mut usize) -> io::Result<PathBuf> {
+        let components: Vec<_> = path.components().collect();
+        let mut resolved = PathBuf::new();
+        for (i, component) in components.iter().enumerate() {
+            match component {
+                Component::CurDir => continue,
+                Component::ParentDir => {
+                    resolved.pop();
+                    continue;
+                }
+                _ => resolved.push(component),
+            }
+            if i + 1 == components.len() && !follow {
+                break;
+            }
+            if let Some(Node::Symlink(target)) = self.nodes.get(&resolved) {
+                *hops += 1;
+                if *hops > MAX_SYMLINK_HOPS {
+                    return Err(io::Error::other("too many levels of symbolic links"));
+                }
+                let link_dir = resolved.parent().unwrap_or(Path::new(""));
+                resolved = self.resolve(&link_dir.join(target), true, hops)?;
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// The entry at `path`, following symlinks if `follow` is set
+    fn node(&self, path: &Path, follow: bool) -> io::Result<(PathBuf, &Node)> {
+        let resolved = self.resolve(path, follow, &mut 0)?;
+        match self.nodes.get(&resolved) {
+            Some(node) => Ok((resolved, node)),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+}
+
+impl Node {
+    fn metadata(&self) -> Metadata {
+        let (file_type, len) = match self {
+            Node::File(contents) => (FileType::File, contents.len() as u64),
+            Node::Dir => (FileType::Dir, 0),
+            Node::Symlink(_) => (FileType::Symlink, 0),
+        };
+        Metadata { file_type, len }
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(self.node(path, true)?.1.metadata())
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        Ok(self.node(path, false)?.1.metadata())
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let (dir, node) = self.node(path, true)?;
+        if !matches!(node, Node::Dir) {
+            return Err(io::Error::from(io::ErrorKind::NotADirectory));
+        }
+        // Everything below `dir` sorts right after it
+        Ok(self
+            .nodes
+            .range(dir.clone()..)
+            .skip(1)
+            .take_while(|(entry, _)| entry.starts_with(&dir))
+            .filter(|(entry, _)| entry.parent() == Some(dir.as_path()))
+            .filter_map(|(entry, _)| entry.file_name())
+            .map(|name| path.join(name))
+            .collect())
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        match self.node(path, true)?.1 {
+            Node::File(contents) => Ok(Box::new(Cursor::new(Arc::clone(contents)))),
+            _ => Err(io::Error::from(io::ErrorKind::IsADirectory)),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(self.node(path, true)?.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_fs() {
+        let fs = MemoryFs::new()
+            .file("/root/a", "hello")
+            .file("/root/sub/b", "")
+            .symlink("/root/link", "sub")
+            .symlink("/root/dangling", "missing");
+
+        let names = |dir: &str| -> Vec<PathBuf> {
+            let mut entries = fs.read_dir(Path::new(dir)).unwrap();
+            entries.sort();
+            entries
+        };
+        assert_eq!(
+            names("/root"),
+            ["/root/a", "/root/dangling", "/root/link", "/root/sub"].map(PathBuf::from)
+        );
+        // Listing through a symlink keeps the link in the paths
+        assert_eq!(names("/root/link"), [PathBuf::from("/root/link/b")]);
+
+        let metadata = fs.metadata(Path::new("/root/a")).unwrap();
+        assert_eq!((metadata.file_type, metadata.len), (FileType::File, 5));
+        let link = Path::new("/root/link");
+        assert_eq!(fs.metadata(link).unwrap().file_type, FileType::Dir);
+        assert_eq!(
+            fs.symlink_metadata(link).unwrap().file_type,
+            FileType::Symlink
+        );
+        assert_eq!(fs.canonicalize(link).unwrap(), Path::new("/root/sub"));
+        assert_eq!(
+            fs.metadata(Path::new("/root/dangling")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let mut contents = String::new();
+        fs.open(Path::new("/root/a"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "hello");
+    }
+}
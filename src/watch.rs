@@ -0,0 +1,269 @@
+//! Watch mode: report duplicates as soon as they appear
+//!
+//! The watched roots are scanned once into a [`ContentSet`], which groups
+//! files by size and hashes them only when a file of the same size shows up.
+//! File system notifications (inotify on Linux) are then collected until a
+//! path has been quiet for a moment, so files are checked once they are
+//! fully written rather than on every write.
+
+use crate::actions::TEMP_SUFFIX;
+use crate::cancel::CancellationToken;
+use crate::error::Error;
+use crate::hash::{Digest, HashAlgorithm};
+use crate::{collect_files, FileInfo};
+use notify::event::{AccessKind, AccessMode, EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+/// A new or modified file whose content already exists elsewhere
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateEvent {
+    pub path: PathBuf,
+    pub size: u64,
+    pub hash: Digest,
+    /// Existing files with the same content, scanned files first by path,
+    /// then files that appeared later in arrival order
+    pub duplicates: Vec<PathBuf>,
+}
+
+/// Known files, grouped by size and hashed on demand
+#[derive(Debug, Default)]
+pub struct ContentSet {
+    files_by_size: HashMap<u64, Vec<FileInfo>>,
+    algorithm: HashAlgorithm,
+}
+
+impl ContentSet {
+    /// Start from every file currently under `roots`, to be compared by
+    /// `algorithm` hashes, adding whatever can't be read to `warnings`
+    pub fn scan(
+        roots: &[PathBuf],
+        algorithm: HashAlgorithm,
+        warnings: &mut Vec<Error>,
+    ) -> anyhow::Result<Self> {
+        let mut files_by_size = HashMap::new();
+        let mut total_files = 0;
+        for root in roots {
+            collect_files(root, &mut files_by_size, &mut total_files, warnings)?;
+        }
+        for files in files_by_size.values_mut() {
+            files.sort_by(|a: &FileInfo, b: &FileInfo| a.path.cmp(&b.path));
+        }
+        Ok(Self {
+            files_by_size,
+            algorithm,
+        })
+    }
+
+    /// Number of files in the set
+    pub fn len(&self) -> usize {
+        self.files_by_size.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add `path` to the set, returning the files it duplicates, if any
+    ///
+    /// Paths that are no longer regular, non-empty files are removed instead.
+    /// Hard links to the same file are not reported as duplicates.
+    pub fn check(&mut self, path: &Path) -> anyhow::Result<Option<DuplicateEvent>> {
+        self.remove(path);
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_file() && metadata.len() > 0 => metadata,
+            _ => return Ok(None),
+        };
+        let size = metadata.len();
+        let mut file = FileInfo::new(path.to_path_buf(), size);
+
+        let candidates = self.files_by_size.entry(size).or_default();
+        if candidates.is_empty() {
+            candidates.push(file);
+            return Ok(None);
+        }
+
+        let algorithm = self.algorithm;
+        let hash = file.calculate_hash_with(algorithm)?;
+        let mut duplicates = Vec::new();
+        candidates.retain_mut(|candidate| {
+            // Hashes are cached, so make sure the copy is still there
+            if !candidate.path.is_file() {
+                return false;
+            }
+            match candidate.calculate_hash_with(algorithm) {
+                Ok(candidate_hash) => {
+                    if candidate_hash == hash && !same_file(&candidate.path, path) {
+                        duplicates.push(candidate.path.clone());
+                    }
+                    true
+                }
+                // Unreadable since it was added
+                Err(_) => false,
+            }
+        });
+        candidates.push(file);
+
+        Ok((!duplicates.is_empty()).then(|| DuplicateEvent {
+            path: path.to_path_buf(),
+            size,
+            hash,
+            duplicates,
+        }))
+    }
+
+    /// Forget `path`, or everything below it if it was a directory
+    pub fn remove(&mut self, path: &Path) {
+        for files in self.files_by_size.values_mut() {
+            files.retain(|f| !f.path.starts_with(path));
+        }
+    }
+}
+
+/// Whether `a` and `b` are hard links to the same file
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// A settled change below a watched root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A file was created or modified
+    Updated(PathBuf),
+    /// A file or directory disappeared
+    Removed(PathBuf),
+    /// The watcher reported a problem; watching goes on
+    Error(String),
+}
+
+/// Watch `roots` and call `on_change` for every file once it has been quiet
+/// for `settle`
+///
+/// Directories that appear (e.g. moved in) are reported file by file. Paths
+/// under `ignore` and temporary files of in-place replacements are skipped.
+/// Runs until `on_change` fails, the watcher stops or `cancel` is
+/// cancelled; changes that haven't settled by then are dropped.
+pub fn watch(
+    roots: &[PathBuf],
+    ignore: &[PathBuf],
+    settle: Duration,
+    cancel: &CancellationToken,
+    mut on_change: impl FnMut(Change) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    for root in roots {
+        watcher.watch(root, RecursiveMode::Recursive)?;
+    }
+
+    let ignored = |path: &Path| {
+        ignore.iter().any(|dir| path.starts_with(dir))
+            || path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().ends_with(TEMP_SUFFIX))
+    };
+
+    // Paths with pending events and when they were last touched
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    while !cancel.is_cancelled() {
+        match rx.recv_timeout(settle / 2) {
+            Ok(Ok(event)) => {
+                let relevant = match event.kind {
+                    EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+                    EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_)) => false,
+                    _ => true,
+                };
+                if relevant {
+                    for path in event.paths.into_iter().filter(|p| !ignored(p)) {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+            }
+            Ok(Err(e)) => on_change(Change::Error(e.to_string()))?,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        let now = Instant::now();
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, touched)| now.duration_since(**touched) >= settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in settled {
+            pending.remove(&path);
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => {
+                    for entry in WalkDir::new(&path)
+                        .follow_links(false)
+                        .into_iter()
+                        .flatten()
+                    {
+                        if entry.file_type().is_file() && !ignored(entry.path()) {
+                            on_change(Change::Updated(entry.into_path()))?;
+                        }
+                    }
+                }
+                Ok(_) => on_change(Change::Updated(path))?,
+                Err(_) => on_change(Change::Removed(path))?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_content_set_reports_new_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("existing.txt"), "shared content").unwrap();
+        fs::write(root.join("other.txt"), "unrelated text").unwrap();
+
+        let mut set = ContentSet::scan(
+            &[root.to_path_buf()],
+            HashAlgorithm::default(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(set.len(), 2);
+
+        let upload = root.join("upload.txt");
+        fs::write(&upload, "shared content").unwrap();
+        let event = set.check(&upload).unwrap().unwrap();
+        assert_eq!(event.duplicates, vec![root.join("existing.txt")]);
+
+        // Checking the same file again doesn't make it its own duplicate
+        assert_eq!(set.check(&upload).unwrap().unwrap().duplicates.len(), 1);
+        assert_eq!(set.len(), 3);
+
+        #[cfg(unix)]
+        {
+            let link = root.join("link.txt");
+            fs::remove_file(&upload).unwrap();
+            set.remove(&upload);
+            fs::hard_link(root.join("existing.txt"), &link).unwrap();
+            assert!(set.check(&link).unwrap().is_none());
+        }
+    }
+}
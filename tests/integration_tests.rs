@@ -1,3 +1,6 @@
+use file_dedup::actions::{apply_action, Action};
+use file_dedup::journal::Journal;
+use file_dedup::session::{GroupStatus, Session};
 use file_dedup::{collect_files, find_duplicate_groups, FileInfo};
 use std::collections::HashMap;
 use std::fs::{self, File};
@@ -113,14 +116,20 @@ fn test_end_to_end_duplicate_detection() {
     let mut total_files = 0;
 
     // Collect all files
-    collect_files(test_dir.path(), &mut files_by_size, &mut total_files, false).unwrap();
+    collect_files(
+        test_dir.path(),
+        &mut files_by_size,
+        &mut total_files,
+        &mut Vec::new(),
+    )
+    .unwrap();
 
     // Should find all non-empty files (ignoring empty files)
     // Files created: photo(3) + doc(2) + unique(3) + same_size(2) + large(2) = 12 files
     assert_eq!(total_files, 12);
 
     // Find duplicate groups
-    let duplicate_groups = find_duplicate_groups(files_by_size, false).unwrap();
+    let duplicate_groups = find_duplicate_groups(files_by_size, &mut Vec::new()).unwrap();
 
     // Should find 3 duplicate groups:
     // 1. Photo files (3 duplicates)
@@ -158,7 +167,11 @@ fn test_cli_report_mode() {
         .output()
         .expect("Failed to run file-dedup");
 
-    assert!(output.status.success(), "CLI should run successfully");
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "duplicates found should exit with 1"
+    );
 
     let stdout = String::from_utf8_lossy(&output.stdout);
 
@@ -178,9 +191,10 @@ fn test_cli_verbose_mode() {
         .output()
         .expect("Failed to run file-dedup with verbose flag");
 
-    assert!(
-        output.status.success(),
-        "CLI with verbose should run successfully"
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "CLI with verbose should report duplicates"
     );
 
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -205,9 +219,10 @@ fn test_cli_no_duplicates_found() {
         .output()
         .expect("Failed to run file-dedup on unique files");
 
-    assert!(
-        output.status.success(),
-        "CLI should run successfully on unique files"
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "CLI should exit with 0 when nothing is found"
     );
 
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -233,7 +248,13 @@ fn test_symlink_security() {
     let mut total_files = 0;
 
     // Should not follow symlinks and only process the real file
-    collect_files(base_path, &mut files_by_size, &mut total_files, false).unwrap();
+    collect_files(
+        base_path,
+        &mut files_by_size,
+        &mut total_files,
+        &mut Vec::new(),
+    )
+    .unwrap();
 
     // Should only find the real file, not the symlink target
     assert_eq!(total_files, 1);
@@ -292,8 +313,633 @@ fn test_multiple_paths_cli() {
         .output()
         .expect("Failed to run file-dedup with multiple paths");
 
-    assert!(output.status.success(), "CLI should handle multiple paths");
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "CLI should handle multiple paths"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Found 1 duplicate groups"));
+}
+
+#[test]
+fn test_cli_sort_and_top() {
+    let temp_dir = TempDir::new().unwrap();
+    create_test_file(temp_dir.path(), "small1.txt", b"small");
+    create_test_file(temp_dir.path(), "small2.txt", b"small");
+    create_test_file(temp_dir.path(), "large1.txt", b"a much larger duplicate");
+    create_test_file(temp_dir.path(), "large2.txt", b"a much larger duplicate");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--no-color",
+            "--sort",
+            "size",
+            "--top",
+            "1",
+            temp_dir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup");
+
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Showing the top 1 of 2 duplicate groups"));
+    assert!(stdout.contains("large1.txt"));
+    assert!(!stdout.contains("small1.txt"));
+    // Only the group shown counts towards the savings
+    assert!(stdout.contains("Potential space savings: 23 B"));
+}
+
+#[test]
+fn test_cli_hash_algorithm() {
+    let temp_dir = TempDir::new().unwrap();
+    create_test_file(temp_dir.path(), "file1.txt", b"abc");
+    create_test_file(temp_dir.path(), "file2.txt", b"abc");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--no-color",
+            "--hash",
+            "sha256",
+            temp_dir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup");
+
+    assert_eq!(output.status.code(), Some(1));
+
+    // First 8 hex digits of SHA-256("abc")
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Hash: ba7816bf"));
+}
+
+#[test]
+fn test_cli_max_memory() {
+    let temp_dir = TempDir::new().unwrap();
+    create_test_file(temp_dir.path(), "a1.txt", b"first");
+    create_test_file(temp_dir.path(), "a2.txt", b"first");
+    create_test_file(temp_dir.path(), "b1.txt", b"second");
+    create_test_file(temp_dir.path(), "b2.txt", b"second");
+    create_test_file(temp_dir.path(), "unique.txt", b"no copies of me");
+
+    // A limit this small puts every size in its own spilled batch
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--no-color",
+            "--max-memory",
+            "0",
+            temp_dir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup");
+
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Found 2 duplicate groups"));
+    assert!(!stdout.contains("unique.txt"));
+}
+
+#[test]
+fn test_cli_size_bounds_and_threads() {
+    let temp_dir = TempDir::new().unwrap();
+    create_test_file(temp_dir.path(), "small1.txt", b"tiny");
+    create_test_file(temp_dir.path(), "small2.txt", b"tiny");
+    create_test_file(temp_dir.path(), "large1.txt", b"somewhat larger");
+    create_test_file(temp_dir.path(), "large2.txt", b"somewhat larger");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--no-color",
+            "--threads",
+            "2",
+            "--min-size",
+            "5",
+            temp_dir.path().to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup");
+
+    assert_eq!(output.status.code(), Some(1));
 
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("Found 1 duplicate groups"));
+    assert!(stdout.contains("large1.txt"));
+    assert!(!stdout.contains("small1.txt"));
+}
+
+#[test]
+fn test_cli_scan_options_only_where_honoured() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().to_str().unwrap();
+    let a = temp_dir.path().join("a");
+    let b = temp_dir.path().join("b");
+    for dir in [&a, &b] {
+        fs::create_dir(dir).unwrap();
+        create_test_file(dir, "small.txt", b"tiny");
+    }
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let run = |args: &[&str]| {
+        Command::new("cargo")
+            .args(["run", "--", "--no-color"])
+            .args(args)
+            .output()
+            .expect("Failed to run file-dedup")
+    };
+
+    // Honoured after the subcommands that scan: a and b only match below it
+    let output = run(&["dirs", "--sort", "path", root]);
+    assert_eq!(output.status.code(), Some(1));
+    let output = run(&["dirs", "--min-size", "5", "--sort", "path", root]);
+    assert_eq!(output.status.code(), Some(0));
+
+    // Rejected where they would be ignored, before or after the subcommand
+    for args in [
+        &["compare", "--min-size", "5", "--source", a, "--target", b][..],
+        &["--top", "1", "compare", "--source", a, "--target", b][..],
+        &["--threads", "2", "dirs", root][..],
+    ] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("error:"));
+    }
+}
+
+#[test]
+fn test_cli_strict_fails_on_skipped_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    create_test_file(temp_dir.path(), "a.txt", b"content");
+    create_test_file(temp_dir.path(), "b.txt", b"content");
+    let missing = temp_dir.path().join("missing");
+
+    let run = |strict: bool| {
+        let mut args = vec!["run", "--", "--no-color"];
+        if strict {
+            args.push("--strict");
+        }
+        args.push(temp_dir.path().to_str().unwrap());
+        args.push(missing.to_str().unwrap());
+        Command::new("cargo")
+            .args(&args)
+            .output()
+            .expect("Failed to run file-dedup")
+    };
+
+    let output = run(false);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Found 1 duplicate groups"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Warning: No such file or directory"));
+
+    let output = run(true);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Found 1 duplicate groups"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("(--strict)"));
+}
+
+#[test]
+fn test_cli_compare_mode() {
+    let temp_dir = TempDir::new().unwrap();
+    let source = temp_dir.path().join("camera");
+    let target = temp_dir.path().join("library");
+    fs::create_dir_all(&source).unwrap();
+    fs::create_dir_all(&target).unwrap();
+    create_test_file(&source, "IMG_0001.jpg", b"already imported");
+    create_test_file(&source, "IMG_0002.jpg", b"brand new picture");
+    create_test_file(&target, "holiday.jpg", b"already imported");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--no-color",
+            "compare",
+            "--source",
+            source.to_str().unwrap(),
+            "--target",
+            target.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup compare");
+
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("IMG_0001.jpg"));
+    assert!(stdout.contains("holiday.jpg"));
+    assert!(!stdout.contains("IMG_0002.jpg"));
+    assert!(stdout.contains("1 of 2 source files already exist in the target"));
+}
+
+#[test]
+fn test_cli_unique_mode() {
+    let temp_dir = TempDir::new().unwrap();
+    let source = temp_dir.path().join("disk");
+    let backup = temp_dir.path().join("backup");
+    fs::create_dir_all(&source).unwrap();
+    fs::create_dir_all(&backup).unwrap();
+    create_test_file(&source, "backed_up.txt", b"safe in the backup");
+    create_test_file(&source, "only_here.txt", b"would be lost");
+    create_test_file(&backup, "copy.txt", b"safe in the backup");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--no-color",
+            "unique",
+            "--source",
+            source.to_str().unwrap(),
+            "--backup",
+            backup.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup unique");
+
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("only_here.txt"));
+    assert!(!stdout.contains("backed_up.txt"));
+    assert!(stdout.contains("Files with no backup: 1"));
+}
+
+#[test]
+fn test_cli_fatal_error_exit_code() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "apply",
+            temp_dir.path().join("no-such-plan.json").to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup apply");
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Error:"));
+}
+
+#[test]
+fn test_cli_undo_from_journal() {
+    let temp_dir = TempDir::new().unwrap();
+    let content = b"Journaled duplicate content";
+    let keeper_path = create_test_file(temp_dir.path(), "keeper.txt", content);
+    let moved_path = create_test_file(temp_dir.path(), "moved.txt", content);
+    let deleted_path = create_test_file(temp_dir.path(), "deleted.txt", content);
+
+    let keeper = FileInfo::new(keeper_path.clone(), content.len() as u64);
+    let moved = FileInfo::new(moved_path.clone(), content.len() as u64);
+    let deleted = FileInfo::new(deleted_path.clone(), content.len() as u64);
+
+    let journal_path = temp_dir.path().join("journal.jsonl");
+    let mut journal = Journal::open(&journal_path).unwrap();
+    let move_action = Action::Move(temp_dir.path().join("trash"));
+    journal
+        .record(&apply_action(&move_action, &moved, &keeper).unwrap())
+        .unwrap();
+    journal
+        .record(&apply_action(&Action::Delete, &deleted, &keeper).unwrap())
+        .unwrap();
+    assert!(!moved_path.exists());
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "undo",
+            "--journal",
+            journal_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup undo");
+
+    assert!(output.status.success(), "undo should run successfully");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(moved_path.exists(), "moved file should be restored");
+    assert!(stdout.contains("Files restored: 1"));
+    assert!(stdout.contains(&format!("recreate it by copying {}", keeper_path.display())));
+
+    // The restore is appended to the journal rather than rewriting it
+    assert_eq!(Journal::read_entries(&journal_path).unwrap().len(), 3);
+}
+
+#[test]
+fn test_cli_resume_session() {
+    let temp_dir = TempDir::new().unwrap();
+    let content = b"Session duplicate content";
+    let done = create_test_file(temp_dir.path(), "done.txt", content);
+    let kept = create_test_file(temp_dir.path(), "kept.txt", content);
+    let removed = create_test_file(temp_dir.path(), "removed.txt", content);
+
+    let mut file_map = HashMap::new();
+    let mut total_files = 0;
+    collect_files(
+        temp_dir.path(),
+        &mut file_map,
+        &mut total_files,
+        &mut Vec::new(),
+    )
+    .unwrap();
+    let groups = find_duplicate_groups(file_map, &mut Vec::new()).unwrap();
+    assert_eq!(groups.len(), 1);
+
+    let session_path = temp_dir.path().join("state/session.json");
+    Session::new(&[temp_dir.path().to_path_buf()], &groups, &Action::Delete)
+        .save(&session_path)
+        .unwrap();
+
+    // Only one copy is left, so resuming settles the group without prompting
+    fs::remove_file(&done).unwrap();
+    fs::remove_file(&removed).unwrap();
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--resume",
+            session_path.to_str().unwrap(),
+            "--journal",
+            temp_dir.path().join("journal.jsonl").to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup --resume");
+
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "resume should run successfully"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Changed since the scan, leaving out"));
+    assert!(stdout.contains("Interactive deduplication complete!"));
+    assert!(kept.exists());
+
+    let session = Session::load(&session_path).unwrap();
+    assert_eq!(session.next_pending(), None);
+    assert_eq!(session.groups[0].status, GroupStatus::Resolved);
+}
+
+#[test]
+fn test_cli_resume_refuses_other_action() {
+    let temp_dir = TempDir::new().unwrap();
+    let content = b"Hardlink session content";
+    let kept = create_test_file(temp_dir.path(), "kept.txt", content);
+    let linked = create_test_file(temp_dir.path(), "linked.txt", content);
+
+    let mut file_map = HashMap::new();
+    let mut total_files = 0;
+    collect_files(
+        temp_dir.path(),
+        &mut file_map,
+        &mut total_files,
+        &mut Vec::new(),
+    )
+    .unwrap();
+    let groups = find_duplicate_groups(file_map, &mut Vec::new()).unwrap();
+
+    let session_path = temp_dir.path().join("session.json");
+    Session::new(&[temp_dir.path().to_path_buf()], &groups, &Action::Hardlink)
+        .save(&session_path)
+        .unwrap();
+
+    // Without --action the default would delete the files
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--resume",
+            session_path.to_str().unwrap(),
+            "--journal",
+            temp_dir.path().join("journal.jsonl").to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup --resume");
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Session was started with --action hardlink"));
+    assert!(kept.exists() && linked.exists());
+    assert_eq!(
+        Session::load(&session_path).unwrap().next_pending(),
+        Some(0)
+    );
+}
+
+#[test]
+fn test_cli_plan_and_apply() {
+    let temp_dir = TempDir::new().unwrap();
+    let scan_dir = temp_dir.path().join("scan");
+    fs::create_dir_all(&scan_dir).unwrap();
+    let content = b"Planned duplicate content";
+    create_test_file(&scan_dir, "a.txt", content);
+    create_test_file(&scan_dir, "b.txt", content);
+    create_test_file(&scan_dir, "c.txt", content);
+
+    let plan_path = temp_dir.path().join("plan.json");
+    let journal_path = temp_dir.path().join("journal.jsonl");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "plan",
+            scan_dir.to_str().unwrap(),
+            "--output",
+            plan_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup plan");
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "plan should run successfully"
+    );
+
+    // Planning alone must not touch anything
+    assert_eq!(fs::read_dir(&scan_dir).unwrap().count(), 3);
+
+    // A reviewer decides to keep one more copy
+    let plan = fs::read_to_string(&plan_path).unwrap();
+    let plan = plan.replacen("\"delete\"", "\"keep\"", 1);
+    fs::write(&plan_path, plan).unwrap();
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "apply",
+            plan_path.to_str().unwrap(),
+            "--journal",
+            journal_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup apply");
+    assert!(output.status.success(), "apply should run successfully");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Actions applied: 1"));
+    assert_eq!(fs::read_dir(&scan_dir).unwrap().count(), 2);
+    assert_eq!(Journal::read_entries(&journal_path).unwrap().len(), 1);
+}
+
+/// `file-dedup` run from `dir` instead of the crate root
+fn file_dedup_in(dir: &std::path::Path, args: &[&str]) -> std::process::Output {
+    let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+    Command::new("cargo")
+        .args(["run", "--quiet", "--manifest-path", manifest, "--"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("Failed to run file-dedup")
+}
+
+#[test]
+fn test_cli_undo_relative_move_from_elsewhere() {
+    let temp_dir = TempDir::new().unwrap();
+    let work = temp_dir.path().join("work");
+    let elsewhere = temp_dir.path().join("elsewhere");
+    fs::create_dir_all(work.join("data")).unwrap();
+    fs::create_dir_all(&elsewhere).unwrap();
+    let content = b"Moved duplicate content";
+    create_test_file(&work.join("data"), "a.txt", content);
+    let moved = create_test_file(&work.join("data"), "b.txt", content);
+    let journal = temp_dir.path().join("journal.jsonl");
+    let journal = journal.to_str().unwrap();
+
+    let output = file_dedup_in(
+        &work,
+        &[
+            "plan",
+            "data",
+            "--output",
+            "plan.json",
+            "--action",
+            "move",
+            "--move-to",
+            "trash",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let output = file_dedup_in(&work, &["apply", "plan.json", "--journal", journal]);
+    assert!(output.status.success());
+    assert!(!moved.exists());
+
+    let entries = Journal::read_entries(std::path::Path::new(journal)).unwrap();
+    assert!(entries[0].destination.as_ref().unwrap().is_absolute());
+
+    let output = file_dedup_in(&elsewhere, &["undo", "--journal", journal]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files restored: 1"));
+    assert!(moved.exists());
+}
+
+#[test]
+fn test_cli_strict_counts_refused_actions() {
+    let temp_dir = TempDir::new().unwrap();
+    let scan_dir = temp_dir.path().join("scan");
+    fs::create_dir_all(&scan_dir).unwrap();
+    create_test_file(&scan_dir, "a.txt", b"Planned duplicate content");
+    let changed = create_test_file(&scan_dir, "b.txt", b"Planned duplicate content");
+    let plan_path = temp_dir.path().join("plan.json");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "plan",
+            scan_dir.to_str().unwrap(),
+            "--output",
+            plan_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup plan");
+    assert_eq!(output.status.code(), Some(1));
+
+    // Same size, different content: apply has to refuse
+    fs::write(&changed, b"PLANNED DUPLICATE CONTENT").unwrap();
+
+    let run = |strict: bool| {
+        let journal_path = temp_dir.path().join("journal.jsonl");
+        let mut args = vec![
+            "run",
+            "--",
+            "apply",
+            plan_path.to_str().unwrap(),
+            "--journal",
+            journal_path.to_str().unwrap(),
+        ];
+        if strict {
+            args.push("--strict");
+        }
+        Command::new("cargo")
+            .args(&args)
+            .output()
+            .expect("Failed to run file-dedup apply")
+    };
+
+    let output = run(false);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Actions skipped: 1"));
+
+    let output = run(true);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("(--strict)"));
+    assert_eq!(fs::read_dir(&scan_dir).unwrap().count(), 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_script_handles_awkward_names() {
+    let temp_dir = TempDir::new().unwrap();
+    let scan_dir = temp_dir.path().join("scan");
+    fs::create_dir_all(&scan_dir).unwrap();
+    let content = b"Scripted duplicate content";
+    create_test_file(&scan_dir, "a_keep.txt", content);
+    let quoted = create_test_file(&scan_dir, "it's a copy.txt", content);
+    let newline = create_test_file(&scan_dir, "line\nbreak.txt", content);
+
+    let script_path = temp_dir.path().join("dedup.sh");
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "script",
+            scan_dir.to_str().unwrap(),
+            "--output",
+            script_path.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run file-dedup script");
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "script should run successfully"
+    );
+
+    // Generating the script must not touch anything
+    assert!(quoted.exists() && newline.exists());
+
+    let status = Command::new("sh")
+        .arg(&script_path)
+        .status()
+        .expect("Failed to run generated script");
+    assert!(status.success(), "generated script should succeed");
+
+    assert_eq!(fs::read_dir(&scan_dir).unwrap().count(), 1);
 }